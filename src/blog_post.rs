@@ -1,13 +1,16 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 use chrono::{Utc};
-use markdown::mdast::Node;
-use markdown::ParseOptions;
+use indexmap::IndexMap;
+use markdown::mdast::{AlignKind, BlockQuote, Definition, FootnoteDefinition, Image, Link as MarkdownLink, Node, ReferenceKind};
+use markdown::{Constructs, ParseOptions};
+use markdown::unist::{Point, Position};
 use serde::{Deserialize, Serialize};
-use crate::blog_post::code_blocks::{QueryResponse, QueryResponseMulti};
-use crate::util::{DisplayExt, VecExt};
-use crate::web::component::{blogpost, html_text, html_paragraph, code_box, html_code, html_heading, html_italics, image_box, html_link, html_span, html_blockquote, footnote_ref, html_raw, html_list, html_checkbox, footnote, html_link_content, html_break, html_strong, html_horizontal_rule};
+use crate::blog_post::code_blocks::CodeBlock;
+use crate::diagnostics::Diagnostic;
+use crate::web::component::{alert, AlertKind, blogpost, html_text, html_paragraph, code_box, html_code, html_heading_anchor, html_italics, image_box, html_image, html_link, html_span, html_blockquote, footnote_ref, html_raw, html_list, html_checkbox, footnote_section, FootnoteEntry, html_link_content, html_break, html_strong, html_horizontal_rule, html_table, ColumnAlignment, html_delete, html_insert, math, html_highlighted, table_of_contents, TocEntry};
 use crate::web::html::{Html};
+use crate::web::math::tex_to_mathml;
 use crate::web::{HRef, Link, RenderContext};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -24,40 +27,80 @@ pub struct BlogMeta {
     #[serde(with = "blog_date_format")]
     pub date: chrono::DateTime<Utc>,
     pub title: String,
-    pub published: Published
+    pub published: Published,
+    #[serde(default)]
+    pub toc: bool,
 }
 
-mod blog_date_format {
+pub mod blog_date_format {
     // from https://serde.rs/custom-date-format.html
-    use chrono::{DateTime, Utc, NaiveDateTime};
+    use chrono::{DateTime, Utc, NaiveDateTime, ParseResult};
     use serde::{self, Deserialize, Serializer, Deserializer};
 
-    const FORMAT: &'static str = "%Y-%m-%d %H:%M:%S %z";
+    pub const FORMAT: &'static str = "%Y-%m-%d %H:%M:%S %z";
+
+    pub fn parse(s: &str) -> ParseResult<DateTime<Utc>> {
+        let dt = NaiveDateTime::parse_from_str(s, FORMAT)?;
+        Ok(DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc))
+    }
 
     pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer, {
         let s = format!("{}", date.format(FORMAT));
         serializer.serialize_str(&s)
     }
 
+    /// Dates are written as a string in [FORMAT], or as a native offset date-time in TOML front matter
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum DateValue {
+        Text(String),
+        Toml(toml::value::Datetime),
+    }
+
     pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error> where D: Deserializer<'de> {
-        let s = String::deserialize(deserializer)?;
-        let dt = NaiveDateTime::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom)?;
-        Ok(DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc))
+        match DateValue::deserialize(deserializer)? {
+            DateValue::Text(s) => parse(&s).map_err(serde::de::Error::custom),
+            DateValue::Toml(datetime) => DateTime::parse_from_rfc3339(&datetime.to_string())
+                .map(|date| date.with_timezone(&Utc))
+                .map_err(|_| serde::de::Error::custom(format!("date `{}` must include a time and offset", datetime)))
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct BlogPost {
     pub metadata: BlogMeta,
+    pub source: PathBuf,
     markdown: String,
+    word_count: usize,
+    excerpt: String,
 }
 
 impl BlogPost {
     pub fn render_content(&self, ctx: &dyn RenderContext) -> [Box<dyn Html>; 1] {
-        let mut post = markdown::to_mdast(&*self.markdown, &ParseOptions::gfm())
+        let mut post = markdown::to_mdast(&*self.markdown, &parse_options())
             .expect("post must be valid markdown to pass build_post");
         remove_non_renderable_nodes(&mut post);
 
+        let mut state = RenderState { source: &self.source, image_hosts: ctx.image_hosts(), snippet_root: ctx.snippet_root(), diagnostics: Vec::new(), heading_ids: HashMap::new(), headings: Vec::new(), footnotes: Vec::new() };
+
+        let mut definitions = HashMap::new();
+        collect_definitions(&post, &mut definitions);
+        resolve_references(&mut post, &definitions);
+        for (label, position) in undefined_labels(&self.markdown) {
+            state.error(format!("reference to undefined label `{}`", label), Some(&position));
+        }
+
+        // Assigned before footnote definitions are taken out of the post, as those are rendered after it
+        collect_heading_ids(&post, &mut state.heading_ids, &mut HashSet::new());
+
+        let mut footnote_definitions = IndexMap::new();
+        take_footnote_definitions(&mut post, &mut footnote_definitions, &mut state);
+        for (label, position) in undefined_footnotes(&self.markdown) {
+            // Brackets starting with `^` are also used as-is, such as in regular expressions
+            state.warning(format!("reference to undefined footnote `{}`", label), Some(&position));
+        }
+
         if let Node::Root(root_node) = post {
             let mut post_contents: Vec<Box<dyn Html>> = Vec::new();
 
@@ -84,18 +127,353 @@ impl BlogPost {
                         html_span(html_text(&*self.metadata.author))
                             .attribute("title", "author")
                     ),
+                    Box::new(html_text(" - ")),
+                    Box::new(
+                        html_span(html_text(format!("{} min read", self.reading_time())))
+                            .attribute("title", format!("{} words", self.word_count))
+                    ),
                 ])))
             );
 
             root_node.children.into_iter()
-                .map(render)
+                .map(|node| render(node, &mut state))
                 .collect_into(&mut post_contents);
 
+            // Footnotes can reference other footnotes, which are appended to `state.footnotes` as they are rendered
+            let mut footnotes = Vec::new();
+            let mut index = 0;
+            while index < state.footnotes.len() {
+                let footnote_id = state.footnotes[index].0.clone();
+                let definition = footnote_definitions.shift_remove(&footnote_id)
+                    .expect("only defined footnotes are parsed as references");
+                footnotes.push((footnote_id, state.render_children(definition.children)));
+                index += 1;
+            }
+            for definition in footnote_definitions.into_values() {
+                state.error(format!("footnote `{}` is never referenced", definition.identifier), definition.position.as_ref());
+            }
+            if footnotes.len() > 0 {
+                let entries = footnotes.into_iter()
+                    .zip(&state.footnotes)
+                    .map(|((footnote_id, content), (_, references))| FootnoteEntry { footnote_id, references: *references, content })
+                    .collect();
+                post_contents.push(Box::new(footnote_section(entries)));
+            }
+
+            if self.metadata.toc && state.headings.len() > 0 {
+                post_contents.insert(1, Box::new(table_of_contents(std::mem::take(&mut state.headings))));
+            }
+
+            state.diagnostics.into_iter().for_each(|diagnostic| ctx.report(diagnostic));
+
             [Box::new(blogpost(post_contents))]
         } else {
             panic!("No root node in markdown {:?}", post);
         }
     }
+
+    pub fn reading_time(&self) -> usize {
+        self.word_count.div_ceil(WORDS_PER_MINUTE).max(1)
+    }
+
+    /// Plain text summary of this post, the paragraphs before a `<!-- more -->` marker or else the first paragraph
+    pub fn excerpt(&self) -> &str {
+        &self.excerpt
+    }
+
+    /// Links to a section of a post are `post#section`, see [BlogPost::heading_ids]
+    pub fn links(&self) -> Vec<(String, Option<Position>)> {
+        let mut links = Vec::new();
+        collect_links(&self.resolved_mdast(), &self.source, &mut links);
+        links
+    }
+
+    pub fn heading_ids(&self) -> HashSet<String> {
+        let mut heading_ids = HashMap::new();
+        collect_heading_ids(&self.resolved_mdast(), &mut heading_ids, &mut HashSet::new());
+        heading_ids.into_values().collect()
+    }
+
+    fn resolved_mdast(&self) -> Node {
+        let mut post = markdown::to_mdast(&*self.markdown, &parse_options())
+            .expect("post must be valid markdown to pass build_post");
+        let mut definitions = HashMap::new();
+        collect_definitions(&post, &mut definitions);
+        resolve_references(&mut post, &definitions);
+        post
+    }
+}
+
+fn resource_id(url: &str) -> String {
+    format!(
+        "resource:{}",
+        Path::new(url)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+    )
+}
+
+enum ImageSource<'a> {
+    Resource(String),
+    External(&'a str),
+}
+
+/// Images are either shared resources, `../resource/image.png`, resources of the post kept in a directory named after it, `post/image.png`, or absolute URLs
+fn image_source<'a>(url: &'a str, source: &Path) -> Option<ImageSource<'a>> {
+    if url.starts_with("../resource") {
+        return Some(ImageSource::Resource(resource_id(url)));
+    }
+    if let Some(address) = url.strip_prefix("https://").or(url.strip_prefix("http://")).or(url.strip_prefix("//")) {
+        let host = address.split(['/', '?', '#']).next().unwrap_or_default();
+        // Strip credentials and port
+        let host = host.rsplit('@').next().unwrap_or_default();
+        let host = host.split(':').next().unwrap_or_default();
+        return (!host.is_empty()).then_some(ImageSource::External(host));
+    }
+    let post_id = source.file_prefix()?.to_str()?;
+    let path = url.strip_prefix("./").unwrap_or(url);
+    path.strip_prefix(post_id)
+        .and_then(|path| path.strip_prefix('/'))
+        .map(|path| ImageSource::Resource(format!(
+            "resource:{}/{}",
+            post_id,
+            Path::new(path)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+        )))
+}
+
+/// Document ID an `intralink:` URL refers to, `post#section` or `#section` for a section of the post at `source`
+fn intralink_target(url: &str, source: &Path) -> Option<String> {
+    let target = url.strip_prefix("intralink:")?;
+    if target.starts_with('#') {
+        let post_id = source.file_prefix()?.to_str()?;
+        Some(format!("{}{}", post_id, target))
+    } else {
+        Some(target.to_string())
+    }
+}
+
+fn collect_links(node: &Node, source: &Path, links: &mut Vec<(String, Option<Position>)>) {
+    match node {
+        Node::Link(link) => if let Some(target) = intralink_target(&link.url, source) {
+            links.push((target, link.position.clone()));
+        },
+        Node::Image(image) => if let Some(ImageSource::Resource(id)) = image_source(&image.url, source) {
+            links.push((id, image.position.clone()));
+        },
+        Node::Code(code) => if let Some(meta) = &code.meta && let Some(Some(page)) = code_meta_tags(meta).remove("info") {
+            links.push((page, code.position.clone()));
+        },
+        _ => {}
+    }
+    if let Some(children) = node.children() {
+        for child in children {
+            collect_links(child, source, links);
+        }
+    }
+}
+
+/// Assigns unique IDs to the headings of a post, by their offset in the markdown, numbering repeated titles such as `setup`, `setup-1`
+fn collect_heading_ids(node: &Node, heading_ids: &mut HashMap<usize, String>, used: &mut HashSet<String>) {
+    if let Node::Heading(heading) = node && let Some(position) = &heading.position {
+        let slug = heading_slug(&heading.children.iter().map(Node::to_string).collect::<String>());
+        let mut id = slug.clone();
+        let mut count = 0;
+        while !used.insert(id.clone()) {
+            count += 1;
+            id = format!("{}-{}", slug, count);
+        }
+        heading_ids.insert(position.start.offset, id);
+    }
+    if let Some(children) = node.children() {
+        for child in children {
+            collect_heading_ids(child, heading_ids, used);
+        }
+    }
+}
+
+fn collect_definitions(node: &Node, definitions: &mut HashMap<String, Definition>) {
+    if let Node::Definition(definition) = node {
+        // The first definition of a label takes precedence
+        definitions.entry(definition.identifier.clone()).or_insert_with(|| definition.clone());
+    }
+    if let Some(children) = node.children() {
+        for child in children {
+            collect_definitions(child, definitions);
+        }
+    }
+}
+
+fn flatten<'a>(node: &'a Node, nodes: &mut Vec<&'a Node>) {
+    nodes.push(node);
+    if let Some(children) = node.children() {
+        for child in children {
+            flatten(child, nodes);
+        }
+    }
+}
+
+fn point_at(markdown: &str, offset: usize) -> Point {
+    let line_start = markdown[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    Point::new(markdown[..offset].matches('\n').count() + 1, markdown[line_start..offset].chars().count() + 1, offset)
+}
+
+/// Parses `markdown` with a placeholder definition appended for every bracketed label, as markdown leaves references to undefined labels and footnotes as plain text
+///
+/// Definitions at or after `markdown.len()` are placeholders
+fn parse_with_placeholders(markdown: &str) -> Option<Node> {
+    let mut labels = HashSet::new();
+    let mut rest = markdown;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        // Alert markers (`[!NOTE]`) are not labels
+        if let Some(end) = rest.find(|c| c == '[' || c == ']') && rest[end..].starts_with(']') && !rest[..end].trim().is_empty() && !rest.starts_with('!') {
+            labels.insert(&rest[..end]);
+        }
+    }
+    let mut placeholders = markdown.to_string();
+    for label in labels {
+        placeholders += &format!("\n\n[{}]: #", label);
+    }
+    markdown::to_mdast(&placeholders, &parse_options()).ok()
+}
+
+fn undefined_labels(markdown: &str) -> Vec<(String, Position)> {
+    let Some(post) = parse_with_placeholders(markdown) else {
+        return Vec::new();
+    };
+
+    let mut nodes = Vec::new();
+    flatten(&post, &mut nodes);
+    let defined = nodes.iter()
+        .filter_map(|node| match node {
+            Node::Definition(definition) if definition.position.as_ref().is_some_and(|position| position.start.offset < markdown.len()) => Some(definition.identifier.as_str()),
+            _ => None
+        })
+        .collect::<HashSet<_>>();
+    nodes.iter()
+        .filter_map(|node| {
+            let (identifier, label, kind, position) = match node {
+                Node::LinkReference(reference) => (&reference.identifier, &reference.label, &reference.reference_kind, reference.position.as_ref()?),
+                Node::ImageReference(reference) => (&reference.identifier, &reference.label, &reference.reference_kind, reference.position.as_ref()?),
+                _ => return None
+            };
+            // Brackets directly after a word are indexing (e.g. `a[0][1]`) rather than a reference
+            if defined.contains(identifier.as_str()) || markdown[..position.start.offset].ends_with(|c: char| c.is_alphanumeric() || c == ']' || c == ')') {
+                return None;
+            }
+            let label_start = match kind {
+                ReferenceKind::Full => position.start.offset + markdown[position.start.offset..position.end.offset].rfind('[')?,
+                _ => position.start.offset
+            };
+            let start = point_at(markdown, label_start);
+            Some((label.clone().unwrap_or_else(|| identifier.clone()), Position { start, end: position.end.clone() }))
+        })
+        .collect()
+}
+
+/// This lets references follow the same `intralink:` and `../resource` rules as inline links and images
+fn resolve_references(node: &mut Node, definitions: &HashMap<String, Definition>) {
+    let resolved = match node {
+        Node::LinkReference(reference) => definitions.get(&reference.identifier)
+            .map(|definition| Node::Link(MarkdownLink {
+                children: std::mem::take(&mut reference.children),
+                position: reference.position.clone(),
+                url: definition.url.clone(),
+                title: definition.title.clone(),
+            })),
+        Node::ImageReference(reference) => definitions.get(&reference.identifier)
+            .map(|definition| Node::Image(Image {
+                position: reference.position.clone(),
+                alt: std::mem::take(&mut reference.alt),
+                url: definition.url.clone(),
+                title: definition.title.clone(),
+            })),
+        _ => None
+    };
+    if let Some(resolved) = resolved {
+        *node = resolved;
+    }
+
+    if let Some(children) = node.children_mut() {
+        children.retain(|child| !matches!(child, Node::Definition(_)));
+        for child in children {
+            resolve_references(child, definitions);
+        }
+    }
+}
+
+fn take_footnote_definitions(node: &mut Node, definitions: &mut IndexMap<String, FootnoteDefinition>, state: &mut RenderState) {
+    if let Some(children) = node.children_mut() {
+        for child in std::mem::take(children) {
+            match child {
+                Node::FootnoteDefinition(definition) if definitions.contains_key(&definition.identifier) => {
+                    state.error(format!("footnote `{}` is defined more than once", definition.identifier), definition.position.as_ref());
+                }
+                Node::FootnoteDefinition(definition) => {
+                    definitions.insert(definition.identifier.clone(), definition);
+                }
+                mut child => {
+                    take_footnote_definitions(&mut child, definitions, state);
+                    children.push(child);
+                }
+            }
+        }
+    }
+}
+
+fn undefined_footnotes(markdown: &str) -> Vec<(String, Position)> {
+    let Some(post) = parse_with_placeholders(markdown) else {
+        return Vec::new();
+    };
+
+    let mut nodes = Vec::new();
+    flatten(&post, &mut nodes);
+    let defined = nodes.iter()
+        .filter_map(|node| match node {
+            Node::FootnoteDefinition(definition) if definition.position.as_ref().is_some_and(|position| position.start.offset < markdown.len()) => Some(definition.identifier.as_str()),
+            _ => None
+        })
+        .collect::<HashSet<_>>();
+    nodes.iter()
+        .filter_map(|node| match node {
+            Node::FootnoteReference(reference) if !defined.contains(reference.identifier.as_str()) =>
+                Some((reference.label.clone().unwrap_or_else(|| reference.identifier.clone()), reference.position.clone()?)),
+            _ => None
+        })
+        .collect()
+}
+
+const WORDS_PER_MINUTE: usize = 200;
+
+fn count_words(node: &Node) -> usize {
+    match node {
+        Node::Text(text) => text.value.split_whitespace().count(),
+        Node::InlineCode(code) => code.value.split_whitespace().count(),
+        _ => node.children()
+            .map(|children| children.iter().map(count_words).sum())
+            .unwrap_or(0)
+    }
+}
+
+fn excerpt(post: &Node) -> String {
+    let children = post.children().map(Vec::as_slice).unwrap_or_default();
+    let is_marker = |node: &Node| matches!(node, Node::Html(html) if html.value.split_whitespace().collect::<String>() == "<!--more-->");
+    let text = match children.iter().position(is_marker) {
+        Some(marker) => children[..marker].iter()
+            .filter(|node| matches!(node, Node::Paragraph(_)))
+            .map(Node::to_string)
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => children.iter()
+            .find(|node| matches!(node, Node::Paragraph(_)))
+            .map(Node::to_string)
+            .unwrap_or_default()
+    };
+    text.split_whitespace().intersperse(" ").collect()
 }
 
 fn remove_non_renderable_nodes(node: &mut Node) {
@@ -106,7 +484,7 @@ fn remove_non_renderable_nodes(node: &mut Node) {
                     return false;
                 }
             }
-            true
+            !matches!(child, Node::Yaml(_) | Node::Toml(_))
         });
         for child in children {
             remove_non_renderable_nodes(child)
@@ -114,13 +492,23 @@ fn remove_non_renderable_nodes(node: &mut Node) {
     }
 }
 
-fn retrieve_meta(post: &Node, meta_list: &mut Vec<String>) {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum MetaSource {
+    Block,
+    YamlFrontMatter,
+    TomlFrontMatter,
+}
+
+fn retrieve_meta(post: &Node, meta_list: &mut Vec<(MetaSource, String, Option<Position>)>) {
     if let Some(children) = post.children() {
         for node in children {
-            if let Node::Code(code) = node {
-                if let Some(meta) = &code.meta && meta == "blogmeta" {
-                    meta_list.push(code.value.clone());
+            match node {
+                Node::Code(code) if code.meta.as_deref() == Some("blogmeta") => {
+                    meta_list.push((MetaSource::Block, code.value.clone(), code.position.clone()));
                 }
+                Node::Yaml(yaml) => meta_list.push((MetaSource::YamlFrontMatter, yaml.value.clone(), yaml.position.clone())),
+                Node::Toml(toml) => meta_list.push((MetaSource::TomlFrontMatter, toml.value.clone(), toml.position.clone())),
+                _ => {}
             }
 
             retrieve_meta(node, meta_list);
@@ -128,27 +516,219 @@ fn retrieve_meta(post: &Node, meta_list: &mut Vec<String>) {
     }
 }
 
-pub fn build_post(markdown: String) -> Result<BlogPost, String> {
-    let mut post = markdown::to_mdast(&*markdown, &ParseOptions::gfm())
-        .map_err(|e| format!("post was not valid markdown {}", e))?;
+fn yaml_diagnostic(message: &str, error: serde_yaml::Error, block_position: Option<&Position>) -> Diagnostic {
+    let diagnostic = Diagnostic::error(format!("{}: {}", message, error));
+    match (error.location(), block_position) {
+        // The YAML starts on the line after the opening fence
+        (Some(location), Some(block)) => diagnostic.line_column(block.start.line + location.line(), location.column()),
+        (_, block) => diagnostic.position(block)
+    }
+}
+
+fn toml_diagnostic(message: &str, toml: &str, error: toml::de::Error, block_position: Option<&Position>) -> Diagnostic {
+    let diagnostic = Diagnostic::error(format!("{}: {}", message, error.message()));
+    match (error.span(), block_position) {
+        // The TOML starts on the line after the opening `+++`
+        (Some(span), Some(block)) => {
+            let before = &toml[..span.start];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+            diagnostic.line_column(block.start.line + line, column)
+        }
+        (_, block) => diagnostic.position(block)
+    }
+}
+
+/// Single dollars are left as text, so prices such as $5 are not math
+fn parse_options() -> ParseOptions {
+    ParseOptions {
+        constructs: Constructs {
+            math_text: true,
+            math_flow: true,
+            frontmatter: true,
+            ..Constructs::gfm()
+        },
+        math_text_single_dollar: false,
+        ..ParseOptions::gfm()
+    }
+}
+
+pub fn build_post(source: PathBuf, markdown: String) -> Result<BlogPost, Diagnostic> {
+    let mut post = markdown::to_mdast(&*markdown, &parse_options())
+        .map_err(|e| Diagnostic::error(format!("post was not valid markdown: {}", e)).source(&source))?;
 
     let mut meta_list = Vec::new();
-    retrieve_meta(&mut post, &mut meta_list);
+    retrieve_meta(&post, &mut meta_list);
 
     if meta_list.len() == 1 {
-        let metadata: BlogMeta = serde_yaml::from_str(&*meta_list[0]).map_err(DisplayExt::display_string)?;
-        Ok(BlogPost { metadata, markdown })
+        let (meta_source, meta, position) = &meta_list[0];
+        let metadata: BlogMeta = match meta_source {
+            MetaSource::Block => serde_yaml::from_str(&*meta)
+                .map_err(|e| yaml_diagnostic("invalid blogmeta", e, position.as_ref()).source(&source))?,
+            MetaSource::YamlFrontMatter => serde_yaml::from_str(&*meta)
+                .map_err(|e| yaml_diagnostic("invalid front matter", e, position.as_ref()).source(&source))?,
+            MetaSource::TomlFrontMatter => toml::from_str(&*meta)
+                .map_err(|e| toml_diagnostic("invalid front matter", &*meta, e, position.as_ref()).source(&source))?
+        };
+        remove_non_renderable_nodes(&mut post);
+        let word_count = count_words(&post);
+        let excerpt = excerpt(&post);
+        Ok(BlogPost { metadata, source, markdown, word_count, excerpt })
     } else if meta_list.len() == 0 {
-        Err("no blogmeta blocks defined")?
+        Err(Diagnostic::error("no front matter or blogmeta block defined").source(&source))
+    } else if meta_list.iter().any(|(meta_source, _, _)| *meta_source != MetaSource::Block) {
+        let (_, _, position) = meta_list.iter().find(|(meta_source, _, _)| *meta_source == MetaSource::Block).unwrap();
+        Err(Diagnostic::error("post defines both front matter and a blogmeta block").source(&source).position(position.as_ref()))
     } else {
-        Err("multiple blogmeta blocks defined")?
+        Err(Diagnostic::error("multiple blogmeta blocks defined").source(&source).position(meta_list[1].2.as_ref()))
     }
 }
 
 mod code_blocks {
+    use std::collections::HashMap;
+    use markdown::unist::Position;
     use serde::{Deserialize, Serialize};
-    use crate::web::component::{code_box, html_bold, html_break, html_horizontal_rule, html_text, tab_box};
-    use crate::web::html::{Component};
+    use crate::diagnostics::Diagnostic;
+    use crate::web::component::{code_box, html_bold, html_break, html_diff, html_highlighted, html_horizontal_rule, html_text, tab_box, DiffLine};
+    use crate::web::html::{Component, Html};
+    use super::yaml_diagnostic;
+
+    pub struct CodeBlock<'a> {
+        pub lang: Option<String>,
+        pub meta_tags: &'a HashMap<String, Option<String>>,
+        pub body: String,
+        pub position: Option<&'a Position>,
+    }
+
+    impl CodeBlock<'_> {
+        pub fn info(&self) -> Option<String> {
+            self.meta_tags.get("info").cloned().flatten()
+        }
+
+        pub fn fold(&self) -> bool {
+            self.meta_tags.contains_key("fold")
+        }
+
+        pub fn preformatted(&self) -> bool {
+            self.meta_tags.contains_key("preformatted")
+        }
+    }
+
+    pub trait CodeBlockFormat: Sync {
+        fn name(&self) -> &'static str;
+
+        fn meta_tags(&self) -> &'static [&'static str] {
+            &[]
+        }
+
+        /// Languages of code blocks rendered in this format without a `format=` tag, such as ```` ```diff ````
+        fn languages(&self) -> &'static [&'static str] {
+            &[]
+        }
+
+        fn render(&self, block: CodeBlock) -> Result<Box<dyn Html>, Diagnostic>;
+    }
+
+    /// Registered code block formats, new formats are added here
+    const FORMATS: &[&dyn CodeBlockFormat] = &[&QueryResponseFormat, &QueryResponseMultiFormat, &DiffFormat];
+
+    pub fn format(name: &str) -> Option<&'static dyn CodeBlockFormat> {
+        FORMATS.iter().copied().find(|format| format.name() == name)
+    }
+
+    pub fn language_format(language: &str) -> Option<&'static dyn CodeBlockFormat> {
+        FORMATS.iter().copied().find(|format| format.languages().contains(&language))
+    }
+
+    /// Query and its response as YAML, `query`, `response` and optionally titles `q_title` and `r_title`
+    struct QueryResponseFormat;
+
+    impl CodeBlockFormat for QueryResponseFormat {
+        fn name(&self) -> &'static str {
+            "query-response"
+        }
+
+        fn render(&self, block: CodeBlock) -> Result<Box<dyn Html>, Diagnostic> {
+            let query_response = serde_yaml::from_str::<QueryResponse>(&block.body)
+                .map_err(|error| yaml_diagnostic("invalid code block yaml query-response", error, block.position))?;
+            Ok(Box::new(query_response.render(block.lang.clone(), block.info(), block.fold(), block.preformatted())))
+        }
+    }
+
+    struct QueryResponseMultiFormat;
+
+    impl CodeBlockFormat for QueryResponseMultiFormat {
+        fn name(&self) -> &'static str {
+            "query-response-multi"
+        }
+
+        fn render(&self, block: CodeBlock) -> Result<Box<dyn Html>, Diagnostic> {
+            let query_response = serde_yaml::from_str::<QueryResponseMulti>(&block.body)
+                .map_err(|error| yaml_diagnostic("invalid code block yaml query-response-multi", error, block.position))?;
+            Ok(Box::new(query_response.render(block.lang.clone(), block.info(), block.fold(), block.preformatted())))
+        }
+    }
+
+    /// Changed lines are highlighted as the block's language, unless it is a diff itself
+    struct DiffFormat;
+
+    impl CodeBlockFormat for DiffFormat {
+        fn name(&self) -> &'static str {
+            "diff"
+        }
+
+        fn languages(&self) -> &'static [&'static str] {
+            &["diff", "patch"]
+        }
+
+        fn render(&self, block: CodeBlock) -> Result<Box<dyn Html>, Diagnostic> {
+            let lines = parse_diff(&block.body).map_err(|(index, message)| {
+                let diagnostic = Diagnostic::error(message);
+                // The body starts on the line after the opening fence
+                match block.position {
+                    Some(position) => diagnostic.line_column(position.start.line + index + 1, 1),
+                    None => diagnostic
+                }
+            })?;
+
+            let language = block.lang.as_deref().filter(|language| !self.languages().contains(language));
+            let diff = html_diff(language, lines);
+            Ok(Box::new(code_box(block.lang.clone(), block.info(), block.fold(), block.preformatted(), diff)))
+        }
+    }
+
+    /// Lines of a unified diff with their kind, without the `+`, `-` or ` ` prefix; errors are the index of the invalid line and a message
+    fn parse_diff(body: &str) -> Result<Vec<(DiffLine, String)>, (usize, String)> {
+        let mut lines = Vec::new();
+        let mut in_hunk = false;
+        for (index, line) in body.lines().enumerate() {
+            let kind = if line.starts_with("@@") {
+                in_hunk = true;
+                DiffLine::Hunk
+            } else if line.starts_with("diff ") {
+                in_hunk = false;
+                DiffLine::Header
+            } else if !in_hunk && ["--- ", "+++ ", "index ", "new file", "deleted file", "old mode", "new mode", "similarity", "rename ", "Binary files"].iter().any(|prefix| line.starts_with(prefix)) {
+                DiffLine::Header
+            } else if line.starts_with('\\') {
+                DiffLine::Note
+            } else {
+                match line.chars().next() {
+                    Some('+') => DiffLine::Added,
+                    Some('-') => DiffLine::Removed,
+                    // Editors often strip the leading space of empty context lines
+                    Some(' ') | None => DiffLine::Context,
+                    Some(_) => return Err((index, format!("invalid diff line `{}`, expected it to start with `+`, `-`, ` ` or `@@`", line)))
+                }
+            };
+            let content = match kind {
+                DiffLine::Added | DiffLine::Removed | DiffLine::Context => line.get(1..).unwrap_or_default(),
+                DiffLine::Header | DiffLine::Hunk | DiffLine::Note => line,
+            };
+            lines.push((kind, content.to_string()));
+        }
+        Ok(lines)
+    }
 
     #[derive(Debug, Serialize, Deserialize)]
     pub struct QueryResponse {
@@ -160,11 +740,12 @@ mod code_blocks {
 
     impl QueryResponse {
         pub fn render(self, lang: Option<String>, info: Option<String>, fold: bool, preformatted: bool) -> Component {
+            let query = html_highlighted(lang.as_deref(), self.query);
             match (self.q_title, self.r_title) {
                 (Some(q_title), Some(r_title)) => code_box(lang, info, fold, preformatted, (
                     html_bold(html_text(q_title)),
                     html_break(),
-                    html_text(self.query),
+                    query,
                     html_horizontal_rule(),
                     html_bold(html_text(r_title)),
                     html_break(),
@@ -173,19 +754,19 @@ mod code_blocks {
                 (Some(q_title), None) => code_box(lang, info, fold, preformatted, (
                     html_bold(html_text(q_title)),
                     html_break(),
-                    html_text(self.query),
+                    query,
                     html_horizontal_rule(),
                     html_text(self.response),
                 )),
                 (None, Some(r_title)) => code_box(lang, info, fold, preformatted, (
-                    html_text(self.query),
+                    query,
                     html_horizontal_rule(),
                     html_bold(html_text(r_title)),
                     html_break(),
                     html_text(self.response),
                 )),
                 (None, None) => code_box(lang, info, fold, preformatted, (
-                    html_text(self.query),
+                    query,
                     html_horizontal_rule(),
                     html_text(self.response),
                 ))
@@ -207,64 +788,266 @@ mod code_blocks {
             )
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::web::component::DiffLine::*;
+        use super::parse_diff;
+
+        #[test]
+        fn headers_and_hunks() {
+            let diff = "diff --git a/x b/x\nindex 1..2 100644\n--- a/x\n+++ b/x\n@@ -1,2 +1,2 @@\n-old\n+new\n keep";
+            assert_eq!(parse_diff(diff), Ok(vec![
+                (Header, "diff --git a/x b/x".to_string()),
+                (Header, "index 1..2 100644".to_string()),
+                (Header, "--- a/x".to_string()),
+                (Header, "+++ b/x".to_string()),
+                (Hunk, "@@ -1,2 +1,2 @@".to_string()),
+                (Removed, "old".to_string()),
+                (Added, "new".to_string()),
+                (Context, "keep".to_string()),
+            ]));
+        }
+
+        #[test]
+        fn header_prefixes_inside_hunks() {
+            // Removing a line that starts with "-- " or adding one that starts with "++ " looks like a file header
+            let diff = "@@ -1 +1 @@\n--- comment\n+++ counter";
+            assert_eq!(parse_diff(diff), Ok(vec![
+                (Hunk, "@@ -1 +1 @@".to_string()),
+                (Removed, "-- comment".to_string()),
+                (Added, "++ counter".to_string()),
+            ]));
+        }
+
+        #[test]
+        fn next_file_ends_hunk() {
+            let diff = "@@ -1 +1 @@\n+a\ndiff --git a/y b/y\n--- a/y\n+++ b/y";
+            assert_eq!(parse_diff(diff).unwrap().into_iter().map(|(kind, _)| kind).collect::<Vec<_>>(), vec![Hunk, Added, Header, Header, Header]);
+        }
+
+        #[test]
+        fn notes_and_empty_context() {
+            let diff = "@@ -1,2 +1,2 @@\n\n-a\n\\ No newline at end of file";
+            assert_eq!(parse_diff(diff), Ok(vec![
+                (Hunk, "@@ -1,2 +1,2 @@".to_string()),
+                (Context, "".to_string()),
+                (Removed, "a".to_string()),
+                (Note, "\\ No newline at end of file".to_string()),
+            ]));
+        }
+
+        #[test]
+        fn unexpected_lines() {
+            assert_eq!(parse_diff("@@ -1 +1 @@\n+a\nnot a diff line"), Err((2, "invalid diff line `not a diff line`, expected it to start with `+`, `-`, ` ` or `@@`".to_string())));
+            assert_eq!(parse_diff("plain text").map_err(|(index, _)| index), Err(0));
+        }
+    }
+}
+
+/// Code block meta tags understood by [render] for all code blocks, other tags not understood by the block's format are reported as likely typos
+const CODE_META_TAGS: [&str; 6] = ["fold", "preformatted", "info", "format", "include", "lines"];
+
+/// Tags of a code block's meta string, `fold info=page` is `fold` without a value and `info` with value `page`
+fn code_meta_tags(meta: &str) -> HashMap<String, Option<String>> {
+    meta.split_ascii_whitespace()
+        .map(|entry| {
+            entry.split_once('=')
+                .map(|(l, r)| (l.to_string(), Some(r.to_string())))
+                .unwrap_or_else(|| (entry.to_string(), None))
+        })
+        .collect()
+}
+
+/// Errors are messages for a diagnostic located at the code block
+fn load_snippet(snippet_root: &Path, path: &str, lines: Option<&Option<String>>) -> Result<String, String> {
+    if !Path::new(path).components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(format!("snippet path `{}` must be relative to the snippet directory, without `..`", path));
+    }
+    let snippet = std::fs::read_to_string(snippet_root.join(path))
+        .map_err(|error| format!("could not read snippet `{}`: {}", path, error))?;
+
+    let range = match lines {
+        None => return Ok(snippet.strip_suffix('\n').unwrap_or(&snippet).to_string()),
+        Some(None) => return Err("code block `lines` tag without range, expected `lines=<first>-<last>`".to_string()),
+        Some(Some(range)) => range,
+    };
+    let invalid_range = || format!("invalid line range `{}`, expected `lines=<first>-<last>` counting from 1", range);
+    let (first, last) = range.split_once('-').unwrap_or((range, range));
+    let first = first.parse::<usize>().map_err(|_| invalid_range())?;
+    let last = last.parse::<usize>().map_err(|_| invalid_range())?;
+    if first == 0 || first > last {
+        return Err(invalid_range());
+    }
+    let line_count = snippet.lines().count();
+    if last > line_count {
+        return Err(format!("line range `{}` is out of bounds of snippet `{}`, which has {} lines", range, path, line_count));
+    }
+    Ok(snippet.lines().skip(first - 1).take(last - first + 1).intersperse("\n").collect())
+}
+
+/// As with GitHub the marker must be on a line of its own, blockquotes with an unknown marker are warned about and rendered as is
+fn take_alert_marker(blockquote: &mut BlockQuote, state: &mut RenderState) -> Option<AlertKind> {
+    let Some(Node::Paragraph(paragraph)) = blockquote.children.first_mut() else { return None };
+    let is_only_child = paragraph.children.len() == 1;
+    let Some(Node::Text(text)) = paragraph.children.first_mut() else { return None };
+    let (marker, rest) = match text.value.split_once('\n') {
+        Some((marker, rest)) => (marker, rest),
+        None if is_only_child => (text.value.as_str(), ""),
+        None => return None
+    };
+    let label = marker.strip_prefix("[!")?.strip_suffix(']')?;
+    let kind = match &*label.to_ascii_uppercase() {
+        "NOTE" => AlertKind::Note,
+        "TIP" => AlertKind::Tip,
+        "IMPORTANT" => AlertKind::Important,
+        "WARNING" => AlertKind::Warning,
+        "CAUTION" => AlertKind::Caution,
+        _ => {
+            state.warning(format!("unknown alert type `{}`, expected one of NOTE, TIP, IMPORTANT, WARNING or CAUTION", label), text.position.as_ref());
+            return None;
+        }
+    };
+
+    text.value = rest.to_string();
+    if text.value.is_empty() {
+        paragraph.children.remove(0);
+        if paragraph.children.is_empty() {
+            blockquote.children.remove(0);
+        }
+    }
+    Some(kind)
+}
+
+/// Lower-case URL fragment for a heading title, such as `heading-anchors` for "Heading Anchors!"
+fn heading_slug(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+    for char in title.chars() {
+        if char.is_alphanumeric() {
+            slug.extend(char.to_lowercase());
+        } else if (char.is_whitespace() || char == '-' || char == '_') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+struct RenderState<'a> {
+    source: &'a Path,
+    image_hosts: Option<&'a [String]>,
+    snippet_root: &'a Path,
+    diagnostics: Vec<Diagnostic>,
+    heading_ids: HashMap<usize, String>,
+    headings: Vec<TocEntry>,
+    /// Referenced footnotes in order of first reference, with the number of references to each
+    footnotes: Vec<(String, usize)>,
+}
+
+impl<'a> RenderState<'a> {
+    /// Reports an error, returning empty content to render in place of the erroneous node
+    fn error<S: Into<String>>(&mut self, message: S, position: Option<&Position>) -> Box<dyn Html> {
+        self.diagnostics.push(Diagnostic::error(message).source(self.source).position(position));
+        Box::new(Vec::<Box<dyn Html>>::new())
+    }
+
+    fn warning<S: Into<String>>(&mut self, message: S, position: Option<&Position>) {
+        self.diagnostics.push(Diagnostic::warning(message).source(self.source).position(position));
+    }
+
+    /// Counts a reference to a footnote, returning the footnote's number and the number of this reference to it
+    fn footnote_reference(&mut self, identifier: &str) -> (usize, usize) {
+        match self.footnotes.iter().position(|(footnote, _)| footnote == identifier) {
+            Some(index) => {
+                self.footnotes[index].1 += 1;
+                (index + 1, self.footnotes[index].1)
+            }
+            None => {
+                self.footnotes.push((identifier.to_string(), 1));
+                (self.footnotes.len(), 1)
+            }
+        }
+    }
+
+    fn render_children(&mut self, children: Vec<Node>) -> Vec<Box<dyn Html>> {
+        children.into_iter()
+            .map(|child| render(child, self))
+            .collect()
+    }
+}
+
+fn render_image(image: Image, is_figure: bool, state: &mut RenderState) -> Box<dyn Html> {
+    let source = match image_source(&image.url, state.source) {
+        Some(ImageSource::Resource(id)) => Link::ID(id),
+        Some(ImageSource::External(host)) => {
+            if let Some(hosts) = state.image_hosts && !hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(host)) {
+                return state.error(format!("image host `{}` is not in the allowed image hosts", host), image.position.as_ref());
+            }
+            Link::Custom { link_title: image.alt.clone(), destination: HRef(image.url.clone()) }
+        }
+        None => return state.error(format!("unknown image url `{}`", image.url), image.position.as_ref())
+    };
+    if image.alt.is_empty() {
+        state.warning(format!("image `{}` has no alt text", image.url), image.position.as_ref());
+    }
+    if is_figure {
+        Box::new(image_box(source, image.alt, image.title))
+    } else {
+        Box::new(html_image(source, image.alt, image.title))
+    }
 }
 
-fn render(node: Node) -> Box<dyn Html> {
+fn render(node: Node, state: &mut RenderState) -> Box<dyn Html> {
     match node {
-        Node::Root(_) => panic!("Nested root in Markdown nodes!"),
-        Node::BlockQuote(blockquote) => Box::new(
-            html_blockquote(blockquote.children.vec_map(render))
-        ),
-        Node::FootnoteDefinition(definition) => Box::new(
-            footnote(&definition.identifier, definition.label.as_ref().unwrap_or(&definition.identifier), definition.children.vec_map(render))
-        ),
+        Node::Root(root) => state.error("nested root in markdown nodes", root.position.as_ref()),
+        Node::BlockQuote(mut blockquote) => match take_alert_marker(&mut blockquote, state) {
+            Some(kind) => Box::new(alert(kind, state.render_children(blockquote.children))),
+            None => Box::new(html_blockquote(state.render_children(blockquote.children)))
+        },
+        // Node::FootnoteDefinition is moved to the footnote section before rendering
         Node::List(list) => Box::new(
-            html_list(list.children.vec_map(render), list.ordered, list.start)
+            html_list(state.render_children(list.children), list.ordered, list.start)
         ),
-        // Node::Toml(toml) => {}
-        // Node::Yaml(yaml) => {}
+        // Node::Toml and Node::Yaml front matter is consumed by build_post
         Node::Break(_) => Box::new(
             html_break()
         ),
         Node::InlineCode(inline_code) => Box::new(
             html_code(html_text(inline_code.value))
         ),
-        // Node::InlineMath(inline_math) => {}
-        // Node::Delete(delete) => {}
-        Node::Emphasis(e) => Box::new(
-            html_italics(e.children.vec_map(render))
+        Node::InlineMath(inline_math) => match tex_to_mathml(&inline_math.value, false) {
+            Ok(mathml) => Box::new(math(mathml, false)),
+            Err(message) => state.error(format!("invalid math: {}", message), inline_math.position.as_ref())
+        },
+        Node::Delete(delete) => Box::new(
+            html_delete(state.render_children(delete.children))
         ),
-        Node::FootnoteReference(reference) => Box::new(
-            footnote_ref(
-                &reference.identifier,
-                reference.label.as_deref().unwrap_or(&reference.identifier),
-            )
+        Node::Emphasis(e) => Box::new(
+            html_italics(state.render_children(e.children))
         ),
+        Node::FootnoteReference(reference) => {
+            let (number, reference_number) = state.footnote_reference(&reference.identifier);
+            Box::new(footnote_ref(&reference.identifier, number, reference_number))
+        }
         Node::Html(html) => Box::new(
             html_raw(html.value)
         ),
-        Node::Image(image) => Box::new(
-            if image.url.starts_with("../resource") {
-                let resource_id = format!(
-                    "resource:{}",
-                    Path::new(&image.url)
-                        .file_stem()
-                        .unwrap()
-                        .to_string_lossy()
-                );
-
-                image_box(Link::ID(resource_id), image.alt, image.title)
-            } else {
-                panic!("Unknown image url `{}`", image.url);
-            }
+        Node::Image(image) => render_image(image, false, state),
+        // Corrected text is written as a strikethrough followed by the correction, `~~old~~[new](ins: "reason")`
+        Node::Link(link) if link.url == "ins:" => Box::new(
+            html_insert(state.render_children(link.children))
+                .attribute_opt("title", link.title)
         ),
-        // Node::ImageReference(image_reference) => {}
         Node::Link(link) => Box::new(
-            if let Some(id) = link.url.strip_prefix("intralink:") {
+            if let Some(id) = intralink_target(&link.url, state.source) {
                 html_link_content(
-                    Link::ID(id.to_string()),
+                    Link::ID(id),
                     link.title,
-                    link.children.vec_map(render),
+                    state.render_children(link.children),
                 )
             } else {
                 html_link_content(
@@ -273,71 +1056,262 @@ fn render(node: Node) -> Box<dyn Html> {
                         destination: HRef(link.url),
                     },
                     link.title,
-                    link.children.vec_map(render),
+                    state.render_children(link.children),
                 )
             }
         ),
-        // Node::LinkReference(link_reference) => {}
         Node::Strong(s) => Box::new(
-            html_strong(s.children.vec_map(render))
+            html_strong(state.render_children(s.children))
         ),
         Node::Text(t) => Box::new(
             html_text(t.value)
         ),
-        Node::Code(code) => Box::new(
-            if let Some(meta) = code.meta {
-                let meta_tags: HashMap<String, Option<String>> = meta.split_ascii_whitespace()
-                    .map(|entry| {
-                        entry.split_once('=')
-                            .map(|(l, r)| (l.to_string(), Some(r.to_string())))
-                            .unwrap_or_else(|| (entry.to_string(), None))
-                    })
-                    .collect();
+        Node::Code(code) => {
+            let meta_tags = code.meta.as_deref().map(code_meta_tags).unwrap_or_default();
+            let format = match meta_tags.get("format") {
+                Some(Some(name)) => match code_blocks::format(name) {
+                    Some(format) => Some(format),
+                    None => return state.error(format!("unknown code block format `{}`", name), code.position.as_ref())
+                },
+                Some(None) => return state.error("code block `format` tag without format, expected `format=<format>`", code.position.as_ref()),
+                None => code.lang.as_deref().and_then(code_blocks::language_format)
+            };
+
+            let mut unknown_tags = meta_tags.keys()
+                .filter(|tag| !CODE_META_TAGS.contains(&tag.as_str()) && !format.is_some_and(|format| format.meta_tags().contains(&tag.as_str())))
+                .collect::<Vec<_>>();
+            unknown_tags.sort();
+            for tag in unknown_tags {
+                state.warning(format!("unknown code block meta tag `{}`", tag), code.position.as_ref());
+            }
+
+            if let Some(None) = meta_tags.get("info") {
+                return state.error("code block `info` tag without page, expected `info=<page>`", code.position.as_ref());
+            }
+
+            let body = match meta_tags.get("include") {
+                Some(Some(path)) => {
+                    if !code.value.trim().is_empty() {
+                        state.warning(format!("code block includes `{}`, its content is ignored", path), code.position.as_ref());
+                    }
+                    match load_snippet(state.snippet_root, path, meta_tags.get("lines")) {
+                        Ok(snippet) => snippet,
+                        Err(message) => return state.error(message, code.position.as_ref())
+                    }
+                }
+                Some(None) => return state.error("code block `include` tag without path, expected `include=<path>`", code.position.as_ref()),
+                None if meta_tags.contains_key("lines") => return state.error("code block `lines` tag without `include`", code.position.as_ref()),
+                None => code.value
+            };
 
-                let fold = meta_tags.contains_key("fold");
-                let preformatted = meta_tags.contains_key("preformatted");
-                let info = meta_tags.get("info").map(|opt| opt.as_ref().expect("info without page").clone());
-
-                if let Some(Some(format)) = meta_tags.get("format") {
-                    match format.as_str() {
-                        "query-response" => {
-                            serde_yaml::from_str::<QueryResponse>(&*code.value)
-                                .expect("invalid code block yaml query-response")
-                                .render(code.lang, info, fold, preformatted)
-                        },
-                        "query-response-multi" => {
-                            serde_yaml::from_str::<QueryResponseMulti>(&*code.value)
-                                .expect("invalid code block yaml query-response-multi")
-                                .render(code.lang, info, fold, preformatted)
-                        },
-                        _ => panic!("Unknown code block format: {}", format)
+            let block = CodeBlock { lang: code.lang, meta_tags: &meta_tags, body, position: code.position.as_ref() };
+            if let Some(format) = format {
+                match format.render(block) {
+                    Ok(content) => content,
+                    Err(diagnostic) => {
+                        state.diagnostics.push(diagnostic.source(state.source));
+                        Box::new(Vec::<Box<dyn Html>>::new())
                     }
-                } else {
-                    code_box(code.lang, info, fold, preformatted, html_text(code.value))
                 }
             } else {
-                code_box(code.lang, None, false, false, html_text(code.value))
+                let (info, fold, preformatted) = (block.info(), block.fold(), block.preformatted());
+                let content = html_highlighted(block.lang.as_deref(), block.body);
+                Box::new(code_box(block.lang, info, fold, preformatted, content))
             }
-        ),
-        // Node::Math(math) => {}
-        Node::Heading(h) => Box::new(
-            html_heading(h.depth as usize, h.children.vec_map(render))
-        ),
-        // Node::Table(table) => {}
+        }
+        Node::Math(display_math) => match tex_to_mathml(&display_math.value, true) {
+            Ok(mathml) => Box::new(math(mathml, true)),
+            Err(message) => state.error(format!("invalid math: {}", message), display_math.position.as_ref())
+        },
+        Node::Heading(h) => {
+            let title = h.children.iter().map(Node::to_string).collect::<String>();
+            let id = h.position.as_ref()
+                .and_then(|position| state.heading_ids.get(&position.start.offset))
+                .cloned()
+                .unwrap_or_else(|| heading_slug(&title));
+            state.headings.push(TocEntry { depth: h.depth as usize, id: id.clone(), title });
+            Box::new(html_heading_anchor(h.depth as usize, id, state.render_children(h.children)))
+        }
+        Node::Table(table) => {
+            let alignment = table.align.iter()
+                .map(|align| match align {
+                    AlignKind::Left => ColumnAlignment::Left,
+                    AlignKind::Center => ColumnAlignment::Center,
+                    AlignKind::Right => ColumnAlignment::Right,
+                    AlignKind::None => ColumnAlignment::Default
+                })
+                .collect();
+            let mut rows = table.children.into_iter()
+                .map(|row| match row {
+                    Node::TableRow(row) => row.children.into_iter()
+                        .map(|cell| match cell {
+                            Node::TableCell(cell) => state.render_children(cell.children),
+                            node => vec![render(node, state)]
+                        })
+                        .collect::<Vec<_>>(),
+                    node => vec![vec![render(node, state)]]
+                })
+                .collect::<Vec<_>>();
+            // The first row of a GFM table is its header
+            let header = if rows.len() > 0 { rows.remove(0) } else { Vec::new() };
+            Box::new(html_table(alignment, header, rows))
+        }
         Node::ThematicBreak(_thematic_break) => Box::new(html_horizontal_rule()),
-        // Node::TableRow(table_row) => {}
-        // Node::TableCell(table_cell) => {}
+        // Node::TableRow and Node::TableCell are rendered as part of their table
         Node::ListItem(list_item) => {
             if let Some(checked) = list_item.checked {
-                Box::new((html_checkbox(checked, false), list_item.children.vec_map(render)))
+                Box::new((html_checkbox(checked, false), state.render_children(list_item.children)))
             } else {
-                Box::new(list_item.children.vec_map(render))
+                Box::new(state.render_children(list_item.children))
             }
         }
-        // Node::Definition(definition) => {}
+        // Node::LinkReference, Node::ImageReference and Node::Definition are resolved into links and images before rendering
+        // A paragraph of only an image is a figure, which cannot be placed in a paragraph
+        Node::Paragraph(mut p) if matches!(&*p.children, [Node::Image(_)]) => {
+            let Some(Node::Image(image)) = p.children.pop() else { unreachable!() };
+            render_image(image, true, state)
+        }
         Node::Paragraph(p) => Box::new(
-            html_paragraph(p.children.vec_map(render))
+            html_paragraph(state.render_children(p.children))
         ),
-        _ => panic!("unknown node type: {:?}", node)
+        _ => {
+            let node_type = format!("{:?}", node).chars().take_while(char::is_ascii_alphanumeric).collect::<String>();
+            state.error(format!("unsupported markdown element `{}`", node_type), node.position())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(markdown: &str) -> Vec<(String, usize, usize)> {
+        undefined_labels(markdown).into_iter()
+            .map(|(label, position)| (label, position.start.line, position.start.column))
+            .collect()
+    }
+
+    #[test]
+    fn undefined_references() {
+        assert_eq!(labels("See [text][missing] here"), vec![("missing".to_string(), 1, 11)]);
+        assert_eq!(labels("See [missing][] here"), vec![("missing".to_string(), 1, 5)]);
+        assert_eq!(labels("First line\nSee [missing] here"), vec![("missing".to_string(), 2, 5)]);
+        assert_eq!(labels("An ![image] here"), vec![("image".to_string(), 1, 4)]);
+    }
+
+    #[test]
+    fn defined_references() {
+        assert_eq!(labels("See [text][label] and [label]\n\n[label]: https://example.com"), vec![]);
+        assert_eq!(labels("See [Label]\n\n[label]: https://example.com"), vec![]);
+    }
+
+    #[test]
+    fn brackets_that_are_not_references() {
+        assert_eq!(labels(r"Escaped \[lit\]\[eral\] brackets"), vec![]);
+        assert_eq!(labels("Indexing a[0][1] and f(x)[2]"), vec![]);
+        assert_eq!(labels("Code `[x]` and\n\n```\n[y]\n```"), vec![]);
+        assert_eq!(labels("- [x] done\n- [ ] todo"), vec![]);
+        assert_eq!(labels("> [!NOTE]\n> An alert"), vec![]);
+        assert_eq!(labels("A link [text](https://example.com)"), vec![]);
+        assert_eq!(labels("A note[^a]"), vec![]);
+    }
+
+    fn footnotes(markdown: &str) -> Vec<(String, usize, usize)> {
+        undefined_footnotes(markdown).into_iter()
+            .map(|(label, position)| (label, position.start.line, position.start.column))
+            .collect()
+    }
+
+    #[test]
+    fn undefined_footnote_references() {
+        assert_eq!(footnotes("A note[^missing] here"), vec![("missing".to_string(), 1, 7)]);
+        assert_eq!(footnotes("A note[^a]\n\n[^a]: With a note[^b]"), vec![("b".to_string(), 3, 18)]);
+        assert_eq!(footnotes("A note[^a]\n\n[^a]: Defined"), vec![]);
+    }
+
+    #[test]
+    fn brackets_that_are_not_footnotes() {
+        assert_eq!(footnotes(r"Escaped \[^x] brackets"), vec![]);
+        assert_eq!(footnotes("Regex `[^a-z]` in code"), vec![]);
+        // Reported, but only as a warning when rendering
+        assert_eq!(footnotes("Regex [^a-z] in text"), vec![("a-z".to_string(), 1, 7)]);
+    }
+
+    #[test]
+    fn dollars_are_not_math() {
+        let mut nodes = Vec::new();
+        let post = markdown::to_mdast("Costs $5 and $10, or $$x^2$$ as math", &parse_options()).unwrap();
+        flatten(&post, &mut nodes);
+        let math = nodes.iter()
+            .filter_map(|node| if let Node::InlineMath(math) = node { Some(math.value.as_str()) } else { None })
+            .collect::<Vec<_>>();
+        assert_eq!(math, vec!["x^2"]);
+    }
+
+    fn snippet_root(test: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("website_builder_{}_{}", test, std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n").unwrap();
+        root
+    }
+
+    fn range(lines: &str) -> Option<Option<String>> {
+        Some(Some(lines.to_string()))
+    }
+
+    #[test]
+    fn snippet_lines() {
+        let root = snippet_root("snippet_lines");
+        assert_eq!(load_snippet(&root, "src/main.rs", None), Ok("fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}".to_string()));
+        assert_eq!(load_snippet(&root, "src/main.rs", range("2-3").as_ref()), Ok("    let x = 1;\n    println!(\"{}\", x);".to_string()));
+        assert_eq!(load_snippet(&root, "src/main.rs", range("4").as_ref()), Ok("}".to_string()));
+        assert_eq!(load_snippet(&root, "src/main.rs", range("1-4").as_ref()), load_snippet(&root, "src/main.rs", None));
+    }
+
+    #[test]
+    fn snippet_paths_outside_root() {
+        let root = snippet_root("snippet_paths_outside_root");
+        assert!(load_snippet(&root.join("src"), "../src/main.rs", None).is_err());
+        assert!(load_snippet(&root, "src/../src/main.rs", None).is_err());
+        assert!(load_snippet(&root, "./src/main.rs", None).is_err());
+        assert!(load_snippet(&root, root.join("src/main.rs").to_str().unwrap(), None).is_err());
+        assert!(load_snippet(&root, "src/missing.rs", None).is_err());
+    }
+
+    #[test]
+    fn invalid_snippet_lines() {
+        let root = snippet_root("invalid_snippet_lines");
+        assert_eq!(load_snippet(&root, "src/main.rs", Some(&None)), Err("code block `lines` tag without range, expected `lines=<first>-<last>`".to_string()));
+        assert_eq!(load_snippet(&root, "src/main.rs", range("0-2").as_ref()), Err("invalid line range `0-2`, expected `lines=<first>-<last>` counting from 1".to_string()));
+        assert_eq!(load_snippet(&root, "src/main.rs", range("3-2").as_ref()), Err("invalid line range `3-2`, expected `lines=<first>-<last>` counting from 1".to_string()));
+        assert!(load_snippet(&root, "src/main.rs", range("a-b").as_ref()).is_err());
+        assert!(load_snippet(&root, "src/main.rs", range("-2").as_ref()).is_err());
+        assert_eq!(load_snippet(&root, "src/main.rs", range("3-5").as_ref()), Err("line range `3-5` is out of bounds of snippet `src/main.rs`, which has 4 lines".to_string()));
+    }
+
+    fn heading_ids(markdown: &str) -> Vec<String> {
+        let post = markdown::to_mdast(markdown, &parse_options()).unwrap();
+        let mut heading_ids = HashMap::new();
+        collect_heading_ids(&post, &mut heading_ids, &mut HashSet::new());
+        let mut heading_ids = heading_ids.into_iter().collect::<Vec<_>>();
+        heading_ids.sort();
+        heading_ids.into_iter().map(|(_, id)| id).collect()
+    }
+
+    #[test]
+    fn heading_slugs() {
+        assert_eq!(heading_slug("Hello World"), "hello-world");
+        assert_eq!(heading_slug("What's new in 1.2?"), "whats-new-in-12");
+        assert_eq!(heading_slug("  snake_case -- and  spaces  "), "snake-case-and-spaces");
+        assert_eq!(heading_slug("Ünïcode Straße"), "ünïcode-straße");
+        assert_eq!(heading_slug("?!"), "section");
+        assert_eq!(heading_slug(""), "section");
+    }
+
+    #[test]
+    fn heading_id_collisions() {
+        assert_eq!(heading_ids("# Intro\n\n## Intro\n\n### Intro"), vec!["intro", "intro-1", "intro-2"]);
+        assert_eq!(heading_ids("# Intro\n\n# Intro\n\n# Intro 1"), vec!["intro", "intro-1", "intro-1-1"]);
+        assert_eq!(heading_ids("# *Emphasis* and `code`\n\n# ?"), vec!["emphasis-and-code", "section"]);
     }
 }
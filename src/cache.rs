@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+/// 64-bit FNV-1a hasher
+///
+/// Hashes are persisted between builds, so this cannot use std's `DefaultHasher` which is not guaranteed stable across Rust releases
+#[derive(Debug, Clone)]
+pub struct ContentHasher(u64);
+
+impl ContentHasher {
+    pub fn new() -> Self {
+        ContentHasher(0xcbf29ce484222325)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> &mut Self {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        self
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Hashes of the content of every output file written by the previous build, by route
+///
+/// Content is hashed rather than the inputs it was rendered from, so that changes to the builder itself are never missed
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildCache {
+    files: BTreeMap<String, u64>,
+}
+
+impl BuildCache {
+    pub fn new() -> Self {
+        BuildCache::default()
+    }
+
+    /// Path of the cache for `output`, next to the output directory so it is never deployed with it
+    ///
+    /// Every output directory has its own cache, so builds into different directories do not invalidate each other
+    pub fn path(output: &Path) -> PathBuf {
+        match output.file_name() {
+            Some(name) => {
+                let mut name = name.to_os_string();
+                name.push(".build-cache.json");
+                output.with_file_name(name)
+            }
+            None => output.join(".build-cache.json"),
+        }
+    }
+
+    /// Loads the cache for `output`, returning `None` if there is no usable cache for that output directory
+    pub fn load(output: &Path) -> Option<Self> {
+        let file = std::fs::File::open(Self::path(output)).ok()?;
+        serde_json::from_reader(file).ok()
+    }
+
+    pub fn save(&self, output: &Path) -> std::io::Result<()> {
+        let file = std::fs::File::create(Self::path(output))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn is_current(&self, route: &str, hash: u64) -> bool {
+        self.files.get(route) == Some(&hash)
+    }
+
+    pub fn insert(&mut self, route: String, hash: u64) {
+        self.files.insert(route, hash);
+    }
+
+    /// Routes in this cache that are not in `current`, i.e. outputs that are no longer built
+    pub fn stale_routes<'a>(&'a self, current: &'a BuildCache) -> impl Iterator<Item=&'a String> + 'a {
+        self.files.keys().filter(|route| !current.files.contains_key(*route))
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use markdown::unist::Position;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error")
+        }
+    }
+}
+
+/// Error or warning found during the build, optionally located in a source file
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub source: Option<PathBuf>,
+    /// 1-indexed line and column in `source`
+    pub position: Option<(usize, usize)>,
+}
+
+impl Diagnostic {
+    pub fn error<S: Into<String>>(message: S) -> Self {
+        Self { severity: Severity::Error, message: message.into(), source: None, position: None }
+    }
+
+    pub fn warning<S: Into<String>>(message: S) -> Self {
+        Self { severity: Severity::Warning, message: message.into(), source: None, position: None }
+    }
+
+    pub fn source(mut self, source: &Path) -> Self {
+        self.source = Some(source.to_path_buf());
+        self
+    }
+
+    pub fn position(mut self, position: Option<&Position>) -> Self {
+        self.position = position.map(|position| (position.start.line, position.start.column));
+        self
+    }
+
+    pub fn line_column(mut self, line: usize, column: usize) -> Self {
+        self.position = Some((line, column));
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)?;
+        match (&self.source, self.position) {
+            (Some(source), Some((line, column))) => write!(f, "\n  --> {}:{}:{}", source.display(), line, column),
+            (Some(source), None) => write!(f, "\n  --> {}", source.display()),
+            (None, _) => Ok(())
+        }
+    }
+}
+
+/// Collects diagnostics over the whole build so all problems are reported at once, rather than stopping at the first
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Adds a diagnostic, ignoring exact duplicates as documents may render the same content more than once (e.g. feeds)
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        if !self.entries.contains(&diagnostic) {
+            self.entries.push(diagnostic);
+        }
+    }
+
+    pub fn extend<I: IntoIterator<Item=Diagnostic>>(&mut self, diagnostics: I) {
+        diagnostics.into_iter().for_each(|diagnostic| self.push(diagnostic));
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.entries.iter().filter(|diagnostic| diagnostic.severity == Severity::Error).count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.entries.iter().filter(|diagnostic| diagnostic.severity == Severity::Warning).count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    /// Writes a compiler-style report of all diagnostics, quoting the source line where available
+    pub fn report(&self, out: &mut dyn Write, include_warnings: bool) -> std::io::Result<()> {
+        let mut sources = HashMap::<&Path, Option<String>>::new();
+
+        let mut entries = self.entries.iter()
+            .filter(|diagnostic| include_warnings || diagnostic.severity == Severity::Error)
+            .collect::<Vec<_>>();
+        entries.sort_by(|left, right| (&left.source, left.position).cmp(&(&right.source, right.position)));
+
+        for diagnostic in entries {
+            writeln!(out, "{}", diagnostic)?;
+
+            if let (Some(source), Some((line, column))) = (&diagnostic.source, diagnostic.position) {
+                let text = sources.entry(source)
+                    .or_insert_with(|| std::fs::read_to_string(source).ok());
+                if let Some(source_line) = text.as_ref().and_then(|text| text.lines().nth(line.saturating_sub(1))) {
+                    let gutter = line.to_string().len();
+                    writeln!(out, "{:gutter$} |", "", gutter = gutter)?;
+                    writeln!(out, "{} | {}", line, source_line)?;
+                    writeln!(out, "{:gutter$} | {:column$}^", "", "", gutter = gutter, column = column.saturating_sub(1))?;
+                }
+            }
+            writeln!(out)?;
+        }
+
+        let (errors, warnings) = (self.error_count(), if include_warnings { self.warning_count() } else { 0 });
+        match (errors, warnings) {
+            (0, 0) => Ok(()),
+            (0, warnings) => writeln!(out, "warning: {} warning(s) emitted", warnings),
+            (errors, warnings) => writeln!(out, "error: build failed with {} error(s) and {} warning(s)", errors, warnings)
+        }
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
@@ -8,12 +8,20 @@
 #![allow(dead_code)]
 
 use std::ffi::OsStr;
+use std::fmt::{Display, Formatter};
 use std::fs::{DirEntry, File};
 use std::io;
-use std::path::{Path};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
 use std::time::Instant;
-use crate::blog_post::Published;
-use crate::website::{Website};
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{ArgAction, Args, Parser, Subcommand};
+use crate::blog_post::{blog_date_format, Published};
+use crate::cache::{BuildCache, ContentHasher};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::website::{RenderedDocument, Website};
 use crate::web::css::{CSSBuilder};
 use crate::website_resource::{Resource, ResourceType};
 
@@ -68,11 +76,19 @@ mod website_resource {
 
 mod util;
 
+mod diagnostics;
+
+mod cache;
+
+mod serve;
+
+/// Error that ends the build early, reported as a [Diagnostic] alongside those collected during the build
 #[derive(Debug)]
 pub enum BuildError {
     IO(io::Error),
     Serde(serde_json::Error),
     String(String),
+    Diagnostic(Diagnostic),
 }
 
 impl From<io::Error> for BuildError {
@@ -93,10 +109,129 @@ impl From<String> for BuildError {
     }
 }
 
-fn main() {
-    let start = Instant::now();
+impl From<Diagnostic> for BuildError {
+    fn from(value: Diagnostic) -> Self {
+        BuildError::Diagnostic(value)
+    }
+}
 
-    println!("Starting website build...");
+impl From<BuildError> for Diagnostic {
+    fn from(value: BuildError) -> Self {
+        match value {
+            BuildError::Diagnostic(diagnostic) => diagnostic,
+            error => Diagnostic::error(error.to_string())
+        }
+    }
+}
+
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::IO(error) => write!(f, "IO error: {}", error),
+            BuildError::Serde(error) => write!(f, "JSON error: {}", error),
+            BuildError::String(error) => write!(f, "{}", error),
+            BuildError::Diagnostic(diagnostic) => write!(f, "{}", diagnostic)
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Builds the static HTML for the website")]
+struct Arguments {
+    #[command(subcommand)]
+    command: Command,
+    /// Print every file processed, repeat for more detail
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Only print errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Build the website into the output directory
+    Build(BuildOptions),
+    /// Remove all files from the output directory
+    Clean {
+        /// Output directory
+        #[arg(short, long, default_value = "./out")]
+        out: PathBuf,
+    },
+    /// Build the website without writing any output
+    Check(BuildOptions),
+    /// Build the website into the serve output directory and serve it over HTTP, rebuilding and reloading open pages when the input root changes
+    Serve {
+        #[command(flatten)]
+        options: BuildOptions,
+        /// Address to serve on
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        address: String,
+        /// Output directory for the served website, kept separate as its pages only work with the local preview server
+        #[arg(long, default_value = "./out-serve")]
+        serve_out: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Args)]
+struct BuildOptions {
+    /// Input root, containing website.json and the posts and resource directories
+    #[arg(short, long, default_value = "./rsc")]
+    root: PathBuf,
+    /// Output directory
+    #[arg(short, long, default_value = "./out")]
+    out: PathBuf,
+    /// Source files code blocks include snippets of, with `include=<path>`
+    #[arg(long, default_value = "./snippets")]
+    snippets: PathBuf,
+    /// Include unpublished posts, building into the drafts output directory instead
+    #[arg(long)]
+    drafts: bool,
+    /// Output directory for builds that include unpublished posts
+    #[arg(long, default_value = "./out-drafts")]
+    drafts_out: PathBuf,
+    /// Build as if at this time rather than now, posts dated later are held back until then; `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS +ZZZZ`
+    #[arg(long, value_parser = parse_now)]
+    now: Option<DateTime<Utc>>,
+    /// Number of documents to render in parallel, defaults to the number of CPUs
+    #[arg(short, long)]
+    jobs: Option<NonZeroUsize>,
+    /// Build pages that reload when the website is rebuilt, only for use with the local preview server
+    #[arg(skip)]
+    live_reload: bool,
+    /// Output directory of builds for the local preview server, replacing the regular output directory
+    #[arg(skip)]
+    serve_out: Option<PathBuf>,
+}
+
+impl BuildOptions {
+    /// Output directory of this build, kept separate for draft and preview server builds so they are never mixed into the published website
+    fn output(&self) -> &Path {
+        if let Some(serve_out) = &self.serve_out {
+            serve_out
+        } else if self.drafts {
+            &self.drafts_out
+        } else {
+            &self.out
+        }
+    }
+}
+
+fn parse_now(value: &str) -> Result<DateTime<Utc>, String> {
+    blog_date_format::parse(value)
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc()))
+        .map_err(|_| format!("expected `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS +ZZZZ`, found `{}`", value))
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+fn base_stylesheet() -> CSSBuilder {
     let mut css = CSSBuilder::new();
     css.import("url('https://fonts.googleapis.com/css2?family=Raleway:wght@100;400&family=Roboto+Mono&display=block')");
 
@@ -112,6 +247,26 @@ fn main() {
         "--colour-secondary-border: #003535",
 
         "--text-colour: #ffffff",
+
+        "--highlight-keyword: #c792ea",
+        "--highlight-type: #ffcb6b",
+        "--highlight-string: #c3e88d",
+        "--highlight-number: #f78c6c",
+        "--highlight-comment: #8a9ba8",
+        "--highlight-literal: #ff9cac",
+        "--highlight-key: #82aaff",
+        "--highlight-variable: #89ddff",
+        "--highlight-macro: #82aaff",
+        "--highlight-attribute: #b2ccd6",
+
+        "--alert-note: #4493f8",
+        "--alert-tip: #3fb950",
+        "--alert-important: #ab7df8",
+        "--alert-warning: #d29922",
+        "--alert-caution: #f85149",
+
+        "--diff-added: #12361e",
+        "--diff-removed: #3d1519",
         "font-size: 16px"
     ]);
 
@@ -127,6 +282,26 @@ fn main() {
         "--colour-secondary-border: black",
 
         "--text-colour: black",
+
+        "--highlight-keyword: #7b1fa2",
+        "--highlight-type: #8a5a00",
+        "--highlight-string: #2e7d32",
+        "--highlight-number: #b33c00",
+        "--highlight-comment: #616161",
+        "--highlight-literal: #ad1457",
+        "--highlight-key: #1a4fa0",
+        "--highlight-variable: #00697a",
+        "--highlight-macro: #1a4fa0",
+        "--highlight-attribute: #5d6d00",
+
+        "--alert-note: #0969da",
+        "--alert-tip: #1a7f37",
+        "--alert-important: #8250df",
+        "--alert-warning: #9a6700",
+        "--alert-caution: #cf222e",
+
+        "--diff-added: #e6ffec",
+        "--diff-removed: #ffebe9",
         "font-size: 12pt"
     ]);
 
@@ -204,95 +379,323 @@ fn main() {
         "width: 100%"
     ]);
 
-    let mut website: Website = serde_json::from_reader(File::open("./rsc/website.json").unwrap()).unwrap();
 
-    for entry in std::fs::read_dir("./rsc/posts/").unwrap() {
-        let path = entry.unwrap().path();
-        if path.extension() == Some(OsStr::new("md")) {
-            let post_id = path.file_prefix().unwrap().to_str()
-                .ok_or_else(|| format!("post {:?} has non-unicode filename", path)).unwrap();
-            println!("\tpost: {:?}", path);
-            let post_string = String::from_utf8(std::fs::read(&path).unwrap())
-                .map_err(|e| format!("post {:?} was not in UTF8 {}", path, e)).unwrap();
+    css
+}
+
+/// Loads the website configuration, posts and resources, returning the website with the IDs and dates of scheduled posts held back from it
+///
+/// Problems with individual posts or resources are collected into `diagnostics` and the offending file skipped, only a missing or invalid website configuration ends the build early
+fn load_website(options: &BuildOptions, verbosity: Verbosity, diagnostics: &mut Diagnostics) -> Result<(Website, Vec<(String, DateTime<Utc>)>), BuildError> {
+    let website_path = options.root.join("website.json");
+    let website_file = File::open(&website_path)
+        .map_err(|e| format!("could not open {:?}: {}", website_path, e))?;
+    let mut website: Website = serde_json::from_reader(website_file)
+        .map_err(|e| Diagnostic::error(format!("invalid website configuration: {}", e)).source(&website_path).line_column(e.line(), e.column()))?;
+    website.live_reload = options.live_reload;
+    website.snippet_root = options.snippets.clone();
+
+    let post_dir = options.root.join("posts");
+    let mut post_resource_dirs = Vec::new();
+    for entry in std::fs::read_dir(&post_dir).map_err(|e| format!("could not read {:?}: {}", post_dir, e))? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            // Images of a post are kept next to it, in a directory named after the post
+            post_resource_dirs.push(entry);
+        } else if path.extension() == Some(OsStr::new("md")) {
+            let Some(post_id) = path.file_prefix().and_then(OsStr::to_str) else {
+                diagnostics.push(Diagnostic::error("post has non-unicode filename").source(&path));
+                continue;
+            };
+            if verbosity >= Verbosity::Verbose {
+                println!("\tpost: {:?}", path);
+            }
+            let post_string = match String::from_utf8(std::fs::read(&path)?) {
+                Ok(post_string) => post_string,
+                Err(e) => {
+                    diagnostics.push(Diagnostic::error(format!("post was not in UTF8: {}", e)).source(&path));
+                    continue;
+                }
+            };
 
-            let post = blog_post::build_post(post_string)
-                .map_err(|e| format!("Error during post {:?} {}", path, e)).unwrap();
+            let post = match blog_post::build_post(path.clone(), post_string) {
+                Ok(post) => post,
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic);
+                    continue;
+                }
+            };
 
-            if post.metadata.published != Published::False {
+            if post.metadata.published != Published::False || options.drafts {
                 if website.posts.insert(post_id.to_string(), post).is_some() {
-                    panic!("duplicate post id {}", post_id);
+                    diagnostics.push(Diagnostic::error(format!("duplicate post id `{}`", post_id)).source(&path));
                 }
             } else {
                 // Drop post
                 continue;
             }
         } else {
-            panic!("Unknown post file type: {:?}", path);
+            diagnostics.push(Diagnostic::error("unknown post file type").source(&path));
         }
     }
+    // Held back before post resources are loaded, so the resources of scheduled posts are not published early
+    let scheduled = hold_back_scheduled(&mut website, options.now.unwrap_or_else(Utc::now), options.drafts);
 
-    fn load_resource(resource_list: &mut Vec<Resource>, prefix: &Path, entry: DirEntry) -> Result<(), BuildError> {
+    fn load_resource(resource_list: &mut Vec<Resource>, prefix: &Path, id_prefix: &str, entry: DirEntry, diagnostics: &mut Diagnostics) -> Result<(), BuildError> {
         let path = entry.path();
         if entry.file_type()?.is_file() {
-            let extension = path.extension().unwrap().to_string_lossy();
+            let extension = path.extension().unwrap_or_default().to_string_lossy();
 
             let resource_type = match &*extension {
                 "svg" => ResourceType::SVG,
                 "png" => ResourceType::PNG,
                 "js" => ResourceType::JS { is_global_script: path.strip_prefix(prefix).is_ok_and(|sub_path| sub_path.starts_with("global script")) },
-                _ => Err(format!("Unknown resource type: {}", extension))?
+                _ => {
+                    diagnostics.push(Diagnostic::error(format!("unknown resource type `{}`", extension)).source(&path));
+                    return Ok(());
+                }
             };
 
             let resource_id = format!(
-                "resource:{}",
+                "resource:{}{}",
+                id_prefix,
                 path.file_stem()
-                    .unwrap()
+                    .unwrap_or_default()
                     .to_string_lossy()
             );
             resource_list.push(Resource::new(resource_type, resource_id, path));
         } else {
             for sub_entry in std::fs::read_dir(path)? {
-                load_resource(resource_list, prefix, sub_entry?)?;
+                load_resource(resource_list, prefix, id_prefix, sub_entry?, diagnostics)?;
             }
         }
         Ok(())
     }
 
-    let resource_dir = Path::new("./rsc/resource/");
-    for entry in std::fs::read_dir(resource_dir).unwrap() {
-        load_resource(&mut website.resources, resource_dir, entry.unwrap()).unwrap();
+    let resource_dir = options.root.join("resource");
+    for entry in std::fs::read_dir(&resource_dir).map_err(|e| format!("could not read {:?}: {}", resource_dir, e))? {
+        load_resource(&mut website.resources, &resource_dir, "", entry?, diagnostics)?;
+    }
+
+    for entry in post_resource_dirs {
+        let path = entry.path();
+        let Some(post_id) = path.file_name().and_then(OsStr::to_str) else {
+            diagnostics.push(Diagnostic::error("post resource directory has non-unicode filename").source(&path));
+            continue;
+        };
+        if website.posts.contains_key(post_id) {
+            if verbosity >= Verbosity::Verbose {
+                println!("	post resources: {:?}", path);
+            }
+            load_resource(&mut website.resources, &path, &format!("{}/", post_id), entry, diagnostics)?;
+        } else if !path.with_extension("md").exists() {
+            diagnostics.push(Diagnostic::error(format!("resource directory of unknown post `{}`", post_id)).source(&path));
+        }
+        // Otherwise the post is unpublished or scheduled and dropped from this build, so are its resources
+    }
+
+    Ok((website, scheduled))
+}
+
+fn clean(out: &Path) -> Result<(), BuildError> {
+    // The cache would otherwise claim the removed files are still current
+    match std::fs::remove_file(BuildCache::path(out)) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => Err(e)?
+    }
+    if !out.exists() {
+        return Ok(());
     }
+    for item in std::fs::read_dir(out)? {
+        let entry = item?;
+        if entry.file_type()?.is_file() {
+            std::fs::remove_file(entry.path())?;
+        } else {
+            std::fs::remove_dir_all(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Scheduled posts are removed entirely, so they have no route and appear in no listing or feed. Draft builds instead preview them as unpublished posts
+fn hold_back_scheduled(website: &mut Website, now: DateTime<Utc>, drafts: bool) -> Vec<(String, DateTime<Utc>)> {
+    let scheduled = website.posts.iter()
+        .filter(|(_, post)| post.metadata.published != Published::False && post.metadata.date > now)
+        .map(|(id, post)| (id.clone(), post.metadata.date))
+        .collect::<Vec<_>>();
 
-    for item in std::fs::read_dir("./out").unwrap() {
-        let entry = item.unwrap();
-        if entry.file_type().unwrap().is_file() {
-            std::fs::remove_file(entry.path()).unwrap();
+    for (id, _) in &scheduled {
+        if drafts {
+            website.posts[id].metadata.published = Published::False;
         } else {
-            std::fs::remove_dir_all(entry.path()).unwrap();
+            website.posts.shift_remove(id);
         }
     }
+    scheduled
+}
+
+fn build(options: &BuildOptions, verbosity: Verbosity, write_output: bool, diagnostics: &mut Diagnostics) -> Result<(), BuildError> {
+    let (website, mut scheduled) = load_website(options, verbosity, diagnostics)?;
 
-    let mut builder = website.build(css).unwrap();
+    let builder = website.build(base_stylesheet(), diagnostics)?;
 
-    while let Some((context, document)) = builder.next() {
-        if let Some(route) = context.route(document.page_ref()) {
-            let directories = "./out/".to_string() + &*route[..route.len() - 1].join("/");
-            std::fs::create_dir_all(directories).unwrap();
-            let mut html_out = File::create("./out/".to_string() + &*route.join("/"))
-                .map_err(|e| format!("error writing file for {:?}: {}", document, e))
-                .unwrap();
+    // Every document is rendered, even if unchanged, as rendering registers the CSS rules used by each page
+    let threads = options.jobs
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    let (documents, render_diagnostics) = builder.render(threads)?;
+    diagnostics.extend(render_diagnostics);
 
-            document.build(context)
-                .render(context, &mut html_out).unwrap();
+    if verbosity >= Verbosity::Verbose {
+        for document in &documents {
+            println!("\tdocument: {}", document.route.join("/"));
+        }
+    }
+
+    // Output of a failed build would be deployed and cached as if it were good, only the preview server shows what could be built
+    if write_output && (!diagnostics.has_errors() || options.live_reload) {
+        write_documents(options, verbosity, documents)?;
+    }
+
+    if scheduled.len() > 0 && verbosity >= Verbosity::Normal {
+        if options.drafts {
+            println!("Included {} scheduled post(s) as drafts:", scheduled.len());
         } else {
-            panic!("Unknown route for: {:?}", document.page_ref());
+            println!("Held back {} scheduled post(s):", scheduled.len());
+        }
+        scheduled.sort_by(|(left_id, left_date), (right_id, right_date)| (left_date, left_id).cmp(&(right_date, right_id)));
+        for (id, date) in scheduled {
+            println!("\t{} (scheduled for {})", id, date.format(blog_date_format::FORMAT));
         }
     }
+    Ok(())
+}
 
-    let mut css_out = File::create("./out/stylesheet.css").unwrap();
-    builder.into_stylesheet().write(&mut css_out).unwrap();
+/// Writes documents whose content changed since the previous build, and removes outputs of documents that are no longer built
+fn write_documents(options: &BuildOptions, verbosity: Verbosity, documents: Vec<RenderedDocument>) -> Result<(), BuildError> {
+    let output = options.output();
+    // Without a cache every file is written, files of documents no longer built are left in place as there is no record of them
+    let previous = BuildCache::load(output);
+    std::fs::create_dir_all(output)?;
+
+    let mut cache = BuildCache::new();
+    let document_count = documents.len();
+    let mut written_count = 0;
+    for document in documents {
+        let route = document.route.join("/");
+        let path = output.join(&route);
+        let hash = ContentHasher::new().write(&document.content).finish();
+        let is_current = previous.as_ref().is_some_and(|previous| previous.is_current(&route, hash));
+        if !is_current || !path.exists() {
+            if verbosity >= Verbosity::Verbose {
+                println!("\twrite: {}", route);
+            }
+            if let Some(directories) = path.parent() {
+                std::fs::create_dir_all(directories)?;
+            }
+            std::fs::write(&path, document.content)
+                .map_err(|e| format!("error writing file {:?}: {}", path, e))?;
+            written_count += 1;
+        }
+        cache.insert(route, hash);
+    }
 
-    let end = Instant::now();
-    let delta = end.duration_since(start).as_secs_f64();
-    println!("Built in {}s", delta);
+    let mut removed_count = 0;
+    if let Some(previous) = &previous {
+        for route in previous.stale_routes(&cache) {
+            let path = output.join(route);
+            if verbosity >= Verbosity::Verbose {
+                println!("\tremove: {}", route);
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => removed_count += 1,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => Err(format!("error removing stale file {:?}: {}", path, e))?
+            }
+            // Remove directories left empty, removal fails and stops at the first non-empty directory
+            let mut parent = path.parent();
+            while let Some(directory) = parent && directory != output && std::fs::remove_dir(directory).is_ok() {
+                parent = directory.parent();
+            }
+        }
+    }
+
+    cache.save(output)
+        .map_err(|e| format!("error writing build cache {:?}: {}", BuildCache::path(output), e))?;
+
+    if verbosity >= Verbosity::Normal {
+        println!("{} of {} files changed, {} removed", written_count, document_count, removed_count);
+    }
+    Ok(())
+}
+
+/// Only returns if the server cannot be started, build errors are reported and the pages that could still be built are served
+fn serve(options: &BuildOptions, address: &str, serve_out: &Path, verbosity: Verbosity) -> Result<(), BuildError> {
+    let options = BuildOptions { live_reload: true, serve_out: Some(serve_out.to_path_buf()), ..options.clone() };
+    let rebuild = || {
+        let start = Instant::now();
+        let mut diagnostics = Diagnostics::new();
+        if let Err(error) = build(&options, verbosity, true, &mut diagnostics) {
+            diagnostics.push(error.into());
+        }
+        if report(&diagnostics, verbosity) && verbosity >= Verbosity::Normal {
+            println!("Built in {}s", Instant::now().duration_since(start).as_secs_f64());
+        }
+    };
+
+    rebuild();
+    let reload = Arc::new(serve::Reload::new());
+    serve::serve(options.output(), address, reload.clone())?;
+    serve::watch(&[&options.root, &options.snippets], || {
+        if verbosity >= Verbosity::Normal {
+            println!("Change detected, rebuilding...");
+        }
+        rebuild();
+        reload.notify();
+    })
+}
+
+fn report(diagnostics: &Diagnostics, verbosity: Verbosity) -> bool {
+    diagnostics.report(&mut io::stderr(), verbosity >= Verbosity::Normal)
+        .expect("could not write diagnostics report");
+    !diagnostics.has_errors()
+}
+
+fn main() -> ExitCode {
+    let arguments = Arguments::parse();
+    let verbosity = match (arguments.quiet, arguments.verbose) {
+        (true, _) => Verbosity::Quiet,
+        (false, 0) => Verbosity::Normal,
+        (false, _) => Verbosity::Verbose
+    };
+
+    let start = Instant::now();
+    let mut diagnostics = Diagnostics::new();
+    let result = match &arguments.command {
+        Command::Build(options) => {
+            if verbosity >= Verbosity::Normal {
+                println!("Starting website build...");
+            }
+            build(options, verbosity, true, &mut diagnostics)
+        }
+        Command::Clean { out } => clean(out),
+        Command::Check(options) => build(options, verbosity, false, &mut diagnostics),
+        Command::Serve { options, address, serve_out } => serve(options, address, serve_out, verbosity),
+    };
+
+    if let Err(error) = result {
+        diagnostics.push(error.into());
+    }
+
+    if report(&diagnostics, verbosity) {
+        if verbosity >= Verbosity::Normal {
+            let delta = Instant::now().duration_since(start).as_secs_f64();
+            println!("Done in {}s", delta);
+        }
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
 }
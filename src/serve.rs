@@ -0,0 +1,211 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+/// Path of the server-sent event stream that notifies pages of rebuilds
+pub const LIVE_RELOAD_PATH: &str = "/__livereload";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Interval between keep-alive messages on idle event streams, so the connections of closed pages are noticed and dropped
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Build counter shared between the watcher and the event streams of open pages
+#[derive(Debug, Default)]
+pub struct Reload {
+    generation: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl Reload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Signals all open pages to reload
+    pub fn notify(&self) {
+        *self.generation.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    fn generation(&self) -> u64 {
+        *self.generation.lock().unwrap()
+    }
+
+    /// Waits for a reload after `generation`, returning false if `timeout` passes first
+    fn wait(&self, generation: u64, timeout: Duration) -> bool {
+        let guard = self.generation.lock().unwrap();
+        let (guard, _) = self.changed.wait_timeout_while(guard, timeout, |current| *current == generation).unwrap();
+        *guard != generation
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("rss") => "application/rss+xml",
+        Some("atom") => "application/atom+xml",
+        Some("json") => "application/feed+json",
+        Some("xml") => "application/xml",
+        _ => "application/octet-stream"
+    }
+}
+
+fn percent_decode(path: &str) -> String {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next().unwrap_or(b'0'), iter.next().unwrap_or(b'0')];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).unwrap_or("00"), 16).unwrap_or(b'?'));
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Maps a request path onto a file in `root`, rejecting paths that would escape it
+fn resolve_path(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let request_path = percent_decode(request_path.split(['?', '#']).next().unwrap_or(""));
+    let relative = Path::new(request_path.trim_start_matches('/'));
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return None;
+    }
+
+    let mut path = root.join(relative);
+    if path.is_dir() {
+        path.push("index.html");
+    }
+    Some(path)
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> std::io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n", status, content_type, body.len())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn live_reload(mut stream: TcpStream, reload: &Reload) -> std::io::Result<()> {
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n")?;
+    stream.flush()?;
+
+    let generation = reload.generation();
+    loop {
+        if reload.wait(generation, KEEPALIVE_INTERVAL) {
+            write!(stream, "event: reload\ndata: \n\n")?;
+            return stream.flush();
+        } else {
+            write!(stream, ": keepalive\n\n")?;
+            stream.flush()?;
+        }
+    }
+}
+
+fn handle(mut stream: TcpStream, root: &Path, reload: &Reload) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Discard headers
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_ascii_whitespace();
+    let (method, request_path) = (parts.next().unwrap_or(""), parts.next().unwrap_or("/"));
+    if method != "GET" && method != "HEAD" {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain", b"405 Method Not Allowed");
+    }
+    if request_path.split('?').next() == Some(LIVE_RELOAD_PATH) {
+        return live_reload(stream, reload);
+    }
+
+    match resolve_path(root, request_path).map(|path| (std::fs::read(&path), path)) {
+        Some((Ok(body), path)) => {
+            let body = if method == "HEAD" { &[][..] } else { &body[..] };
+            respond(&mut stream, "200 OK", content_type(&path), body)
+        }
+        Some((Err(_), _)) => respond(&mut stream, "404 Not Found", "text/plain", b"404 Not Found"),
+        None => respond(&mut stream, "400 Bad Request", "text/plain", b"400 Bad Request")
+    }
+}
+
+/// Serves the files in `root` over HTTP on a background thread
+///
+/// Pages built with live reload are reloaded whenever `reload` is notified
+pub fn serve(root: &Path, address: &str, reload: Arc<Reload>) -> std::io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(address)?;
+    println!("Serving {:?} on http://{}/", root, listener.local_addr()?);
+
+    let root = root.to_path_buf();
+    Ok(std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    eprintln!("error accepting connection: {}", error);
+                    continue;
+                }
+            };
+            let (root, reload) = (root.clone(), reload.clone());
+            std::thread::spawn(move || {
+                if let Err(error) = handle(stream, &root, &reload) {
+                    eprintln!("error serving request: {}", error);
+                }
+            });
+        }
+    }))
+}
+
+/// Lists the modification time and size of every file under `directory`
+fn snapshot(directory: &Path, files: &mut Vec<(PathBuf, Option<SystemTime>, u64)>) {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => snapshot(&entry.path(), files),
+            Ok(metadata) => files.push((entry.path(), metadata.modified().ok(), metadata.len())),
+            Err(_) => {}
+        }
+    }
+}
+
+/// Polls the files under `roots`, calling `on_change` after every change
+pub fn watch<F: FnMut()>(roots: &[&Path], mut on_change: F) -> ! {
+    let take_snapshot = || {
+        let mut files = Vec::new();
+        for root in roots {
+            snapshot(root, &mut files);
+        }
+        files.sort();
+        files
+    };
+
+    let mut previous = take_snapshot();
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let mut current = take_snapshot();
+        if current != previous {
+            // Editors may save in several steps, wait for the files to settle before rebuilding
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                let next = take_snapshot();
+                if next == current {
+                    break;
+                }
+                current = next;
+            }
+            on_change();
+            previous = current;
+        }
+    }
+}
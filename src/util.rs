@@ -1,5 +1,5 @@
+use std::cell::Cell;
 use std::fmt::Display;
-use std::sync::atomic::{AtomicU64, Ordering};
 
 #[allow(dead_code)]
 #[derive(Debug, Eq, PartialEq)]
@@ -56,8 +56,16 @@ impl<T: Display> DisplayExt for T {
 }
 
 
-static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+thread_local! {
+    static ID_COUNTER: Cell<u64> = Cell::new(0);
+}
 
+/// Returns an ID unique within the document being rendered on this thread
 pub fn next_unique_id() -> u64 {
-    ID_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ID_COUNTER.with(|counter| counter.replace(counter.get() + 1))
+}
+
+/// Restarts unique IDs, called before rendering each document so IDs do not depend on which thread renders it, or in which order
+pub fn reset_unique_ids() {
+    ID_COUNTER.with(|counter| counter.set(0));
 }
\ No newline at end of file
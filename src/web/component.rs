@@ -3,9 +3,11 @@ use std::vec;
 use chrono::{DateTime, Utc};
 use crate::util;
 use crate::util::{DisplayExt, Language, VecExt};
-use crate::web::html::{Component, Html, HtmlElement, HtmlFormat, HtmlPlaintext, RawHtml, Tag};
+use crate::web::html::{escape, Component, Html, HtmlElement, HtmlFormat, HtmlPlaintext, RawHtml, Tag};
 use crate::web::{HRef, Link, RenderContext};
-use crate::web::css::{CSSQuery, CSSRule};
+use crate::web::css::{CSSCallback, CSSQuery, CSSRule};
+use crate::web::feed::FeedFormat;
+use crate::web::highlight::highlight;
 
 pub fn html_raw<S: Into<String>>(text: S) -> RawHtml {
     RawHtml(text.into())
@@ -51,6 +53,18 @@ pub fn html_strong<C: Html + 'static>(content: C) -> HtmlElement {
         .content(content)
 }
 
+pub fn html_delete<C: Html + 'static>(content: C) -> HtmlElement {
+    element("del")
+        .inline(true)
+        .content(content)
+}
+
+pub fn html_insert<C: Html + 'static>(content: C) -> HtmlElement {
+    element("ins")
+        .inline(true)
+        .content(content)
+}
+
 pub fn html_preformatted<C: Html + 'static>(content: C) -> HtmlElement {
     element("pre")
         .inline(true)
@@ -178,6 +192,10 @@ pub struct Image {
     title: Option<String>,
 }
 
+pub fn html_image(source: Link, alt_text: String, title: Option<String>) -> Image {
+    Image { source, alt_text, title }
+}
+
 impl Html for Image {
     fn is_inline(&self, _context: &mut dyn RenderContext) -> bool {
         true
@@ -224,6 +242,34 @@ pub fn html_heading<C: Html + 'static>(depth: usize, content: C) -> HtmlElement
         .content(content)
 }
 
+pub fn html_heading_anchor<C: Html + 'static>(depth: usize, id: String, content: C) -> Component {
+    fn anchor_style() -> CSSRule {
+        (CSSQuery::None, ".heading_anchor", Box::new([
+            "margin-left: 0.5rem",
+            "text-decoration: none",
+            "visibility: hidden"
+        ]))
+    }
+    fn anchor_visible_style() -> CSSRule {
+        (CSSQuery::None, ".heading:hover > .heading_anchor, .heading_anchor:focus-within", Box::new([
+            "visibility: visible"
+        ]))
+    }
+
+    let anchor = html_link(
+        Link::Custom {
+            link_title: "§".to_string(),
+            destination: HRef(format!("#{}", id)),
+        },
+        Some("Permalink".to_string()),
+    );
+    Component {
+        content: html_heading(depth, (content, element("span").attribute("class", "heading_anchor").content(anchor)))
+            .attribute("id", id),
+        style: vec![anchor_style, anchor_visible_style],
+    }
+}
+
 component!(html_list, [], fn(items: Vec<Box<dyn Html>>, ordered: bool, start: Option<u32>) {
     element(if ordered { "ol" } else { "ul" })
     .content(
@@ -235,6 +281,139 @@ component!(html_list, [], fn(items: Vec<Box<dyn Html>>, ordered: bool, start: Op
     "flex-direction: column"
 ]);
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ColumnAlignment {
+    Default,
+    Left,
+    Center,
+    Right,
+}
+
+impl ColumnAlignment {
+    fn class(self) -> Option<&'static str> {
+        match self {
+            ColumnAlignment::Default => None,
+            ColumnAlignment::Left => Some("table_align-left"),
+            ColumnAlignment::Center => Some("table_align-center"),
+            ColumnAlignment::Right => Some("table_align-right")
+        }
+    }
+}
+
+pub fn html_table<C: Html + 'static>(alignment: Vec<ColumnAlignment>, header: Vec<C>, rows: Vec<Vec<C>>) -> Component {
+    fn box_style() -> CSSRule {
+        (CSSQuery::None, ".table-box", Box::new([
+            "max-width: 100%",
+            "overflow-x: auto",
+            "align-self: center",
+            "margin: 0.5rem"
+        ]))
+    }
+    fn table_style() -> CSSRule {
+        (CSSQuery::None, ".table-box > table", Box::new([
+            "border-collapse: collapse"
+        ]))
+    }
+    fn cell_style() -> CSSRule {
+        (CSSQuery::None, ".table-box th, .table-box td", Box::new([
+            "border: 0.125rem solid var(--colour-secondary-border)",
+            "padding: 0.25rem 0.5rem"
+        ]))
+    }
+    fn header_style() -> CSSRule {
+        (CSSQuery::None, ".table-box th", Box::new([
+            "background: var(--colour-secondary)"
+        ]))
+    }
+    fn align_left_style() -> CSSRule {
+        (CSSQuery::None, ".table_align-left", Box::new([
+            "text-align: left"
+        ]))
+    }
+    fn align_center_style() -> CSSRule {
+        (CSSQuery::None, ".table_align-center", Box::new([
+            "text-align: center"
+        ]))
+    }
+    fn align_right_style() -> CSSRule {
+        (CSSQuery::None, ".table_align-right", Box::new([
+            "text-align: right"
+        ]))
+    }
+
+    fn row<C: Html + 'static>(alignment: &[ColumnAlignment], is_header: bool, cells: Vec<C>) -> HtmlElement {
+        element("tr").content(
+            cells.into_iter()
+                .enumerate()
+                .map(|(index, cell)| {
+                    element(if is_header { "th" } else { "td" })
+                        .attribute_opt("scope", if is_header { Some("col") } else { None })
+                        .attribute_opt("class", alignment.get(index).and_then(|alignment| alignment.class()))
+                        .content(cell)
+                })
+                .collect::<Vec<_>>()
+        )
+    }
+
+    Component {
+        content: element("div")
+            .attribute("class", "table-box")
+            .content(
+                element("table")
+                    .content(element("thead").content(row(&alignment, true, header)))
+                    .content(element("tbody").content(rows.vec_map(|cells| row(&alignment, false, cells))))
+            ),
+        style: vec![box_style, table_style, cell_style, header_style, align_left_style, align_center_style, align_right_style],
+    }
+}
+
+pub fn math(mathml: String, display: bool) -> Component {
+    fn inline_style() -> CSSRule {
+        (CSSQuery::None, ".math-inline", Box::new([
+            "white-space: nowrap"
+        ]))
+    }
+    fn display_style() -> CSSRule {
+        (CSSQuery::None, ".math-display", Box::new([
+            "max-width: 100%",
+            "overflow-x: auto",
+            "overflow-y: hidden",
+            "margin: 0.5rem 0"
+        ]))
+    }
+    fn font_style() -> CSSRule {
+        (CSSQuery::None, ".math-inline > math, .math-display > math", Box::new([
+            "font-family: math",
+            "font-size: 1.1em"
+        ]))
+    }
+    fn align_left_style() -> CSSRule {
+        (CSSQuery::None, ".math_align-left", Box::new([
+            "text-align: left"
+        ]))
+    }
+    fn align_right_style() -> CSSRule {
+        (CSSQuery::None, ".math_align-right", Box::new([
+            "text-align: right"
+        ]))
+    }
+
+    let content = if display {
+        element("div")
+            .attribute("class", "math-display")
+            .content(html_raw(mathml))
+    } else {
+        element("span")
+            .attribute("class", "math-inline")
+            .inline(true)
+            .content(html_raw(mathml))
+    };
+    Component {
+        content,
+        style: vec![inline_style, display_style, font_style, align_left_style, align_right_style],
+    }
+}
+
 component!(content_column, [], [
     "display: flex",
     "flex-direction: column",
@@ -250,19 +429,28 @@ component!(title, [font_title], fn(name: String) { html_text(name) }, [
     "padding: 1rem"
 ]);
 
-pub fn footnote_ref(footnote_id: &str, text: &str) -> Component {
+component!(draft_banner, [font_head], fn() { html_text("DRAFT - this post is not published") }, [
+    "font-size: 2rem",
+    "text-align: center",
+    "background: var(--colour-secondary)",
+    "border: 0.25rem dashed var(--colour-secondary-border)",
+    "padding: 0.5rem"
+]);
+
+/// The `reference`th reference to the footnote numbered `number`, counting from 1
+pub fn footnote_ref(footnote_id: &str, number: usize, reference: usize) -> Component {
     Component {
         content: element("sup")
             .inline(true)
-            .attribute("id", format!("footnote_ref-{}", footnote_id))
+            .attribute("id", format!("footnote_ref-{}-{}", footnote_id, reference))
             .attribute("class", "footnote_ref")
             .content(
                 html_link(
                     Link::Custom {
-                        link_title: text.to_string(),
+                        link_title: number.to_string(),
                         destination: HRef(format!("#footnote-{}", footnote_id)),
                     },
-                    Some(format!("footnote {}", footnote_id)),
+                    Some(format!("footnote {}", number)),
                 )
             ),
         style: vec![
@@ -273,38 +461,147 @@ pub fn footnote_ref(footnote_id: &str, text: &str) -> Component {
     }
 }
 
-pub fn footnote<C: Html + 'static>(footnote_id: &str, text: &str, content: C) -> Component {
+pub struct FootnoteEntry {
+    pub(crate) footnote_id: String,
+    pub(crate) references: usize,
+    pub(crate) content: Vec<Box<dyn Html>>,
+}
+
+pub fn footnote_section(footnotes: Vec<FootnoteEntry>) -> Component {
     fn style() -> CSSRule {
-        (CSSQuery::None, ".footnote", Box::new([
+        (CSSQuery::None, ".footnotes", Box::new([
             "font-size: 0.75rem",
         ]))
     }
+    fn list_style() -> CSSRule {
+        (CSSQuery::None, ".footnotes > ol", Box::new([
+            "margin: 0",
+            "padding-left: 1.5rem"
+        ]))
+    }
 
     Component {
-        content: element("div")
-            .attribute("id", format!("footnote-{}", footnote_id))
-            .attribute("class", "footnote")
+        content: element("section")
+            .attribute("class", "footnotes")
             .content((
                 html_horizontal_rule(),
-                html_text(text),
-                html_link(
-                    Link::Custom {
-                        link_title: "↵".to_string(),
-                        destination: HRef(format!("#footnote_ref-{}", footnote_id)),
-                    },
-                    Some("Return to post".to_string()),
-                ),
-                content
+                element("ol").content(footnotes.vec_map(|FootnoteEntry { footnote_id, references, content }| {
+                    let back_links = (1..=references)
+                        .map(|reference| (
+                            html_text(" "),
+                            html_link(
+                                Link::Custom {
+                                    link_title: if references == 1 { "↵".to_string() } else { format!("↵{}", reference) },
+                                    destination: HRef(format!("#footnote_ref-{}-{}", footnote_id, reference)),
+                                },
+                                Some(if references == 1 { "Return to post".to_string() } else { format!("Return to reference {}", reference) }),
+                            )
+                        ))
+                        .collect::<Vec<_>>();
+                    element("li")
+                        .attribute("id", format!("footnote-{}", footnote_id))
+                        .content(content)
+                        .content(html_span(back_links))
+                }))
             )),
-        style: vec![style],
+        style: vec![style, list_style],
     }
 }
 
-pub fn image_box(source: Link, alt_text: String, title: Option<String>) -> Component {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AlertKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl AlertKind {
+    fn class(self) -> &'static str {
+        match self {
+            AlertKind::Note => "alert_note",
+            AlertKind::Tip => "alert_tip",
+            AlertKind::Important => "alert_important",
+            AlertKind::Warning => "alert_warning",
+            AlertKind::Caution => "alert_caution",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            AlertKind::Note => "Note",
+            AlertKind::Tip => "Tip",
+            AlertKind::Important => "Important",
+            AlertKind::Warning => "Warning",
+            AlertKind::Caution => "Caution",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            AlertKind::Note => "ℹ",
+            AlertKind::Tip => "✦",
+            AlertKind::Important => "❢",
+            AlertKind::Warning => "⚠",
+            AlertKind::Caution => "⛔",
+        }
+    }
+}
+
+pub fn alert<C: Html + 'static>(kind: AlertKind, content: C) -> Component {
+    fn alert_style() -> CSSRule {
+        (CSSQuery::None, ".alert", Box::new([
+            "padding: 0.5rem 1rem",
+            "background: var(--colour-secondary)",
+            "border-left: 0.25rem solid var(--alert-colour)",
+        ]))
+    }
+    fn title_style() -> CSSRule {
+        (CSSQuery::None, ".alert_title", Box::new([
+            "margin: 0",
+            "font-weight: bold",
+            "color: var(--alert-colour)",
+        ]))
+    }
+    fn icon_style() -> CSSRule {
+        (CSSQuery::None, ".alert_icon", Box::new([
+            "margin-right: 0.5rem",
+        ]))
+    }
+    let kind_style: CSSCallback = match kind {
+        AlertKind::Note => || (CSSQuery::None, ".alert_note", Box::new(["--alert-colour: var(--alert-note)"])),
+        AlertKind::Tip => || (CSSQuery::None, ".alert_tip", Box::new(["--alert-colour: var(--alert-tip)"])),
+        AlertKind::Important => || (CSSQuery::None, ".alert_important", Box::new(["--alert-colour: var(--alert-important)"])),
+        AlertKind::Warning => || (CSSQuery::None, ".alert_warning", Box::new(["--alert-colour: var(--alert-warning)"])),
+        AlertKind::Caution => || (CSSQuery::None, ".alert_caution", Box::new(["--alert-colour: var(--alert-caution)"])),
+    };
+
     Component {
         content: element("div")
+            .attribute("class", format!("alert {}", kind.class()))
+            .content((
+                element("p")
+                    .attribute("class", "alert_title")
+                    .content((
+                        element("span")
+                            .attribute("class", "alert_icon")
+                            .attribute("aria-hidden", "true")
+                            .content(html_text(kind.icon())),
+                        html_text(kind.title()),
+                    )),
+                content,
+            )),
+        style: vec![alert_style, title_style, icon_style, kind_style],
+    }
+}
+
+pub fn image_box(source: Link, alt_text: String, title: Option<String>) -> Component {
+    let caption = title.map(|title| element("figcaption").content(html_text(title))).into_iter().collect::<Vec<_>>();
+    Component {
+        content: element("figure")
             .attribute("class", "image-box")
-            .content(Image { source, alt_text, title }),
+            .content((Image { source, alt_text, title: None }, caption)),
         style: vec![
             || (CSSQuery::None, ".image-box", Box::new([
                 "display: flex",
@@ -313,9 +610,14 @@ pub fn image_box(source: Link, alt_text: String, title: Option<String>) -> Compo
                 "align-self: center",
                 "background: var(--colour-secondary)",
                 "border: 0.25rem solid var(--colour-secondary-border)",
-                "max-width: 100%"
+                "max-width: 100%",
+                "margin: 0",
             ])),
             || (CSSQuery::None, ".image-box img", Box::new(["width: 100%"])),
+            || (CSSQuery::None, ".image-box figcaption", Box::new([
+                "padding: 0.25rem 0.5rem",
+                "font-style: italic",
+            ])),
         ],
     }
 }
@@ -412,6 +714,61 @@ pub fn tab_box<C: Html + 'static>(tabs: Vec<(String, C)>) -> Component {
     }
 }
 
+pub fn html_highlighted(language: Option<&str>, code: String) -> [Box<dyn Html>; 1] {
+    fn keyword_style() -> CSSRule {
+        (CSSQuery::None, ".highlight_keyword", Box::new(["color: var(--highlight-keyword)"]))
+    }
+    fn type_style() -> CSSRule {
+        (CSSQuery::None, ".highlight_type", Box::new(["color: var(--highlight-type)"]))
+    }
+    fn string_style() -> CSSRule {
+        (CSSQuery::None, ".highlight_string", Box::new(["color: var(--highlight-string)"]))
+    }
+    fn number_style() -> CSSRule {
+        (CSSQuery::None, ".highlight_number", Box::new(["color: var(--highlight-number)"]))
+    }
+    fn comment_style() -> CSSRule {
+        (CSSQuery::None, ".highlight_comment", Box::new([
+            "color: var(--highlight-comment)",
+            "font-style: italic"
+        ]))
+    }
+    fn literal_style() -> CSSRule {
+        (CSSQuery::None, ".highlight_literal", Box::new(["color: var(--highlight-literal)"]))
+    }
+    fn key_style() -> CSSRule {
+        (CSSQuery::None, ".highlight_key", Box::new(["color: var(--highlight-key)"]))
+    }
+    fn variable_style() -> CSSRule {
+        (CSSQuery::None, ".highlight_variable", Box::new(["color: var(--highlight-variable)"]))
+    }
+    fn macro_style() -> CSSRule {
+        (CSSQuery::None, ".highlight_macro", Box::new(["color: var(--highlight-macro)"]))
+    }
+    fn attribute_style() -> CSSRule {
+        (CSSQuery::None, ".highlight_attribute", Box::new(["color: var(--highlight-attribute)"]))
+    }
+
+    let Some(tokens) = language.and_then(|language| highlight(language, &code)) else {
+        return [Box::new(html_text(code))];
+    };
+
+    // Written as raw HTML as separate elements would be laid out with whitespace between tokens
+    let html = tokens.into_iter()
+        .map(|(kind, text)| match kind {
+            Some(kind) => format!(r#"<span class="{}">{}</span>"#, kind.class(), escape(text)),
+            None => escape(text)
+        })
+        .collect::<String>();
+    [Box::new(Component {
+        content: element("span")
+            .attribute("class", "highlight")
+            .inline(true)
+            .content(html_raw(html)),
+        style: vec![keyword_style, type_style, string_style, number_style, comment_style, literal_style, key_style, variable_style, macro_style, attribute_style],
+    })]
+}
+
 pub fn code_box<C: Html + 'static>(title: Option<String>, info: Option<String>, fold: bool, preformatted: bool, content: C) -> Component {
     fn box_style() -> CSSRule {
         (CSSQuery::None, ".code-box", Box::new([
@@ -537,6 +894,103 @@ pub fn code_box<C: Html + 'static>(title: Option<String>, info: Option<String>,
     }
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DiffLine {
+    /// File header, such as `--- a/file`
+    Header,
+    /// Hunk header, `@@ -1,2 +1,3 @@`
+    Hunk,
+    Added,
+    Removed,
+    Context,
+    /// Note on the previous line, such as `\ No newline at end of file`
+    Note,
+}
+
+impl DiffLine {
+    fn class(self) -> &'static str {
+        match self {
+            DiffLine::Header => "diff_line diff_header",
+            DiffLine::Hunk => "diff_line diff_hunk",
+            DiffLine::Added => "diff_line diff_added",
+            DiffLine::Removed => "diff_line diff_removed",
+            DiffLine::Context => "diff_line diff_context",
+            DiffLine::Note => "diff_line diff_note",
+        }
+    }
+
+    fn gutter(self) -> Option<&'static str> {
+        match self {
+            DiffLine::Added => Some("+"),
+            DiffLine::Removed => Some("-"),
+            DiffLine::Context => Some(" "),
+            DiffLine::Header | DiffLine::Hunk | DiffLine::Note => None,
+        }
+    }
+}
+
+pub fn html_diff(language: Option<&str>, lines: Vec<(DiffLine, String)>) -> Component {
+    fn diff_style() -> CSSRule {
+        (CSSQuery::None, ".diff, .diff_line", Box::new(["display: block"]))
+    }
+    fn added_style() -> CSSRule {
+        (CSSQuery::None, ".diff_added", Box::new(["background: var(--diff-added)"]))
+    }
+    fn removed_style() -> CSSRule {
+        (CSSQuery::None, ".diff_removed", Box::new(["background: var(--diff-removed)"]))
+    }
+    fn hunk_style() -> CSSRule {
+        (CSSQuery::None, ".diff_hunk", Box::new([
+            "color: var(--highlight-comment)",
+            "border-top: 1px solid var(--colour-secondary-border)",
+            "border-bottom: 1px solid var(--colour-secondary-border)",
+            "margin: 0.25rem 0",
+        ]))
+    }
+    fn header_style() -> CSSRule {
+        (CSSQuery::None, ".diff_header", Box::new(["font-weight: bold"]))
+    }
+    fn note_style() -> CSSRule {
+        (CSSQuery::None, ".diff_note", Box::new([
+            "color: var(--highlight-comment)",
+            "font-style: italic",
+        ]))
+    }
+    fn gutter_style() -> CSSRule {
+        (CSSQuery::None, ".diff_gutter", Box::new([
+            "display: inline-block",
+            "width: 2ch",
+        ]))
+    }
+
+    let lines = lines.into_iter()
+        .map(|(kind, line)| {
+            let content: Box<dyn Html> = match kind {
+                DiffLine::Added | DiffLine::Removed | DiffLine::Context => Box::new(html_highlighted(language, line)),
+                DiffLine::Header | DiffLine::Hunk | DiffLine::Note => Box::new(html_text(line)),
+            };
+            element("span")
+                .attribute("class", kind.class())
+                .inline(true)
+                .content_opt(kind.gutter().map(|gutter| {
+                    element("span")
+                        .attribute("class", "diff_gutter")
+                        .inline(true)
+                        .content(html_text(gutter))
+                }))
+                .content([content])
+        })
+        .collect::<Vec<_>>();
+
+    Component {
+        content: element("span")
+            .attribute("class", "diff")
+            .inline(true)
+            .content(lines),
+        style: vec![diff_style, added_style, removed_style, hunk_style, header_style, note_style, gutter_style],
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum NavigationItem {
     SingleLink(Link),
@@ -696,10 +1150,58 @@ pub fn blogpost<C: Html + 'static>(content: C) -> Component {
     }
 }
 
+pub struct TocEntry {
+    pub(crate) depth: usize,
+    pub(crate) id: String,
+    pub(crate) title: String,
+}
+
+pub fn table_of_contents(entries: Vec<TocEntry>) -> Component {
+    fn style() -> CSSRule {
+        (CSSQuery::None, ".table-of-contents", Box::new([
+            "align-self: flex-start",
+            "background: var(--colour-secondary)",
+            "border: 0.25rem solid var(--colour-secondary-border)",
+            "padding: 0.5rem"
+        ]))
+    }
+    fn list_style() -> CSSRule {
+        (CSSQuery::None, ".table-of-contents ol", Box::new([
+            "margin: 0",
+            "padding-left: 1.5rem"
+        ]))
+    }
+
+    /// Lists the entries deeper than `parent_depth`, nesting each entry's deeper successors under it
+    fn list(entries: &mut std::iter::Peekable<vec::IntoIter<TocEntry>>, parent_depth: usize) -> HtmlElement {
+        let mut ordered_list = element("ol");
+        while let Some(TocEntry { depth, id, title }) = entries.next_if(|entry| entry.depth > parent_depth) {
+            let mut item = element("li")
+                .content(html_link(Link::Custom { link_title: title, destination: HRef(format!("#{}", id)) }, None));
+            if entries.peek().is_some_and(|next| next.depth > depth) {
+                item = item.content(list(entries, depth));
+            }
+            ordered_list = ordered_list.content(item);
+        }
+        ordered_list
+    }
+
+    Component {
+        content: element("nav")
+            .attribute("class", "table-of-contents")
+            .content((
+                html_bold(html_text("Contents")),
+                list(&mut entries.into_iter().peekable(), 0)
+            )),
+        style: vec![style, list_style],
+    }
+}
+
 pub struct PostListEntry<'a> {
     pub(crate) post_id: &'a str,
     pub(crate) post_date: &'a DateTime<Utc>,
     pub(crate) post_title: &'a str,
+    pub(crate) post_excerpt: &'a str,
     // TODO: Maybe category?
 }
 
@@ -708,7 +1210,7 @@ component!(postlist, [], fn(post_list: Vec<PostListEntry>) {
         html_text("Posts"),
         if post_list.len() > 0 {
             element("lo").content(
-                post_list.vec_map(|PostListEntry { post_id, post_date, post_title }| {
+                post_list.vec_map(|PostListEntry { post_id, post_date, post_title, post_excerpt }| {
                     element("li")
                         .content(LinkText {
                             link: Link::ID(post_id.to_string()),
@@ -717,6 +1219,7 @@ component!(postlist, [], fn(post_list: Vec<PostListEntry>) {
                                 format!("{} - {}", post_date.format("%Y-%b-%d"), post_title)
                             }))
                         })
+                        .content_opt((!post_excerpt.is_empty()).then(|| element("p").content(html_text(post_excerpt))))
                 })
             )
         } else {
@@ -752,7 +1255,7 @@ pub fn content_bottom_spacer() -> Component {
     }
 }
 
-pub fn page<B: Html + 'static>(stylesheet: HRef, scripts: Vec<HRef>, lang: &Language, title: String, no_robots: bool, body: B) -> impl Html {
+pub fn page<B: Html + 'static>(stylesheet: HRef, scripts: Vec<HRef>, feeds: Vec<(String, FeedFormat, HRef)>, live_reload: Option<HRef>, lang: &Language, title: String, no_robots: bool, body: B) -> impl Html {
     [
         element("!DOCTYPE")
             .attribute("html", ()),
@@ -775,10 +1278,22 @@ pub fn page<B: Html + 'static>(stylesheet: HRef, scripts: Vec<HRef>, lang: &Lang
                             element("link")
                                 .attribute("href", stylesheet)
                                 .attribute("rel", "stylesheet"),
-                        ].extend_chain(scripts.into_iter().map(|href| {
+                        ].extend_chain(feeds.into_iter().map(|(title, format, href)| {
+                            element("link")
+                                .attribute("type", format.mime_type())
+                                .attribute("href", href)
+                                .attribute("title", format!("{} ({})", title, format.title()))
+                                .attribute("rel", "alternate")
+                        })).extend_chain(scripts.into_iter().map(|href| {
                             element("script")
                                 .attribute("src", href)
                                 .attribute("defer", ())
+                        })).extend_chain(live_reload.into_iter().map(|href| {
+                            element("script")
+                                .content(html_raw(format!(
+                                    "new EventSource({:?}).addEventListener(\"reload\", () => location.reload());",
+                                    href.0
+                                )))
                         }))
                     ),
                 element("body")
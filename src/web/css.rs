@@ -33,6 +33,12 @@ impl CSSBuilder {
         self.rules.insert(generator);
     }
 
+    /// Adds the imports and rules of `other` after those already registered, keeping the first registration of duplicates
+    pub fn merge(&mut self, other: CSSBuilder) {
+        self.imports.extend(other.imports);
+        self.rules.extend(other.rules);
+    }
+
     fn escape_identifier(identifier: &str) -> String {
         use std::fmt::Write;
         let mut string = String::with_capacity(identifier.len());
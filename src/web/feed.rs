@@ -0,0 +1,345 @@
+use std::io::Write;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+use crate::blog_post::BlogPost;
+use crate::web::{Link, Renderable, RenderContext};
+use crate::web::html::{escape, Html, HtmlFormat};
+
+/// Scheme of `url` (e.g. `https`, `mailto` or `data`), if it has one
+fn scheme(url: &str) -> Option<&str> {
+    let scheme = &url[..url.find(':')?];
+    let mut chars = scheme.chars();
+    let is_scheme = chars.next().is_some_and(|char| char.is_ascii_alphabetic())
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '+' || char == '-' || char == '.');
+    is_scheme.then_some(scheme)
+}
+
+/// Resolves `href` as found on the page at `page_url` into an absolute URL
+pub fn absolute_url(href: &str, page_url: &str) -> String {
+    let page_url = &page_url[..page_url.find('#').unwrap_or(page_url.len())];
+    if scheme(href).is_some() {
+        return href.to_string();
+    } else if href.starts_with("//") {
+        return match scheme(page_url) {
+            Some(scheme) => format!("{}:{}", scheme, href),
+            None => href.to_string()
+        };
+    } else if href.is_empty() || href.starts_with('#') {
+        return format!("{}{}", page_url, href);
+    }
+    let page_url = &page_url[..page_url.find('?').unwrap_or(page_url.len())];
+    if href.starts_with('?') {
+        return format!("{}{}", page_url, href);
+    }
+
+    let (origin, page_path) = match page_url.find("://") {
+        Some(scheme_end) => match page_url[scheme_end + 3..].find('/') {
+            Some(host_length) => page_url.split_at(scheme_end + 3 + host_length),
+            None => (page_url, "")
+        },
+        // Without a scheme there is no origin, the page URL is only a path
+        None => ("", page_url)
+    };
+    let page_path = page_path.trim_start_matches('/');
+    let (href_path, suffix) = href.split_at(href.find(|c| c == '?' || c == '#').unwrap_or(href.len()));
+
+    let mut segments: Vec<&str> = if href_path.starts_with('/') {
+        Vec::new()
+    } else {
+        let mut segments = page_path.split('/').collect::<Vec<_>>();
+        segments.pop();
+        segments
+    };
+
+    for segment in href_path.trim_start_matches('/').split('/') {
+        match segment {
+            "." => {}
+            ".." => { segments.pop(); }
+            segment => segments.push(segment)
+        }
+    }
+
+    format!("{}/{}{}", origin, segments.join("/"), suffix)
+}
+
+/// Rewrites all `href` and `src` attributes in rendered HTML to absolute URLs, see [absolute_url]
+pub fn absolute_links(html: &str, page_url: &str) -> String {
+    const ATTRIBUTES: [&str; 2] = [" href=\"", " src=\""];
+
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(value_start) = ATTRIBUTES.iter().filter_map(|attribute| rest.find(attribute).map(|i| i + attribute.len())).min() {
+        out.push_str(&rest[..value_start]);
+        rest = &rest[value_start..];
+        let value_end = rest.find('"').unwrap_or(rest.len());
+        out.push_str(&absolute_url(&rest[..value_end], page_url));
+        rest = &rest[value_end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    pub const ALL: [FeedFormat; 3] = [FeedFormat::Rss, FeedFormat::Atom, FeedFormat::Json];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "rss",
+            FeedFormat::Atom => "atom",
+            FeedFormat::Json => "json"
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "RSS",
+            FeedFormat::Atom => "Atom",
+            FeedFormat::Json => "JSON Feed"
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => ".rss",
+            FeedFormat::Atom => ".atom",
+            FeedFormat::Json => ".json"
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml",
+            FeedFormat::Atom => "application/atom+xml",
+            FeedFormat::Json => "application/feed+json"
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FeedItem {
+    pub(crate) link: Link,
+    pub(crate) post: BlogPost,
+}
+
+impl FeedItem {
+    /// Renders the full post content as a HTML string with absolute links
+    ///
+    /// Content is rendered as if on the post's own page, so links resolve and diagnostics are reported the same as for that page
+    fn content(&self, context: &mut dyn RenderContext) -> std::io::Result<String> {
+        let Link::ID(post_id) = &self.link else {
+            panic!("feed item must link to a post: {:?}", self.link);
+        };
+        let feed_page = context.current_page().0.to_string();
+
+        context.set_current_page(post_id);
+        let mut buffer = Vec::new();
+        let result = self.post.render_content(&*context)
+            .build(context, &mut buffer, HtmlFormat::Indent(0));
+        context.set_current_page(&feed_page);
+        result?;
+
+        let html = String::from_utf8(buffer).expect("rendered HTML must be UTF-8");
+        Ok(absolute_links(&html, &context.resolve_url(&self.link).0))
+    }
+}
+
+/// Selection of posts to syndicate, independent of the format it is serialized to
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub(crate) title: String,
+    pub(crate) description: String,
+    /// Page the feed is the syndication of, e.g. the category index
+    pub(crate) link: Link,
+    pub(crate) items: Vec<FeedItem>,
+}
+
+impl Feed {
+    fn updated(&self) -> DateTime<Utc> {
+        self.items.iter()
+            .map(|item| item.post.metadata.date)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+pub struct FeedRender {
+    pub(crate) format: FeedFormat,
+    pub(crate) feed: Feed,
+}
+
+impl FeedRender {
+    fn render_rss(feed: Feed, context: &mut dyn RenderContext, out: &mut dyn Write) -> std::io::Result<()> {
+        let self_url = context.resolve_url(&Link::ID(context.current_page().0.to_string()));
+
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/elements/1.1/">"#)?;
+        writeln!(out, "    <channel>")?;
+        writeln!(out, "        <title>{}</title>", escape(&feed.title))?;
+        writeln!(out, "        <link>{}</link>", escape(&context.resolve_url(&feed.link).0))?;
+        writeln!(out, "        <description>{}</description>", escape(&feed.description))?;
+        writeln!(out, r#"        <atom:link href="{}" rel="self" type="{}"/>"#, escape(&self_url.0), FeedFormat::Rss.mime_type())?;
+        if feed.items.len() > 0 {
+            writeln!(out, "        <pubDate>{}</pubDate>", feed.updated().to_rfc2822())?;
+        }
+
+        for item in &feed.items {
+            let metadata = &item.post.metadata;
+            let url = context.resolve_url(&item.link);
+            let category = context.resolve_category(&metadata.category).title.clone();
+            let content = item.content(context)?;
+            writeln!(out, "        <item>")?;
+            writeln!(out, "            <title>{}</title>", escape(&metadata.title))?;
+            writeln!(out, "            <link>{}</link>", escape(&url.0))?;
+            writeln!(out, r#"            <guid isPermaLink="true">{}</guid>"#, escape(&url.0))?;
+            writeln!(out, "            <pubDate>{}</pubDate>", metadata.date.to_rfc2822())?;
+            writeln!(out, "            <dc:creator>{}</dc:creator>", escape(&metadata.author))?;
+            writeln!(out, "            <category>{}</category>", escape(&category))?;
+            writeln!(out, "            <description>{}</description>", escape(&content))?;
+            writeln!(out, "        </item>")?;
+        }
+
+        writeln!(out, "    </channel>")?;
+        writeln!(out, "</rss>")
+    }
+
+    fn render_atom(feed: Feed, context: &mut dyn RenderContext, out: &mut dyn Write) -> std::io::Result<()> {
+        let self_url = context.resolve_url(&Link::ID(context.current_page().0.to_string()));
+
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, r#"<feed xmlns="http://www.w3.org/2005/Atom">"#)?;
+        writeln!(out, "    <title>{}</title>", escape(&feed.title))?;
+        writeln!(out, "    <subtitle>{}</subtitle>", escape(&feed.description))?;
+        writeln!(out, r#"    <link href="{}" rel="alternate" type="text/html"/>"#, escape(&context.resolve_url(&feed.link).0))?;
+        writeln!(out, r#"    <link href="{}" rel="self" type="{}"/>"#, escape(&self_url.0), FeedFormat::Atom.mime_type())?;
+        writeln!(out, "    <id>{}</id>", escape(&self_url.0))?;
+        writeln!(out, "    <updated>{}</updated>", feed.updated().to_rfc3339_opts(SecondsFormat::Secs, true))?;
+
+        for item in &feed.items {
+            let metadata = &item.post.metadata;
+            let url = context.resolve_url(&item.link);
+            let category = context.resolve_category(&metadata.category).title.clone();
+            let content = item.content(context)?;
+            let date = metadata.date.to_rfc3339_opts(SecondsFormat::Secs, true);
+            writeln!(out, "    <entry>")?;
+            writeln!(out, "        <title>{}</title>", escape(&metadata.title))?;
+            writeln!(out, r#"        <link href="{}" rel="alternate" type="text/html"/>"#, escape(&url.0))?;
+            writeln!(out, "        <id>{}</id>", escape(&url.0))?;
+            writeln!(out, "        <published>{}</published>", date)?;
+            writeln!(out, "        <updated>{}</updated>", date)?;
+            writeln!(out, "        <author><name>{}</name></author>", escape(&metadata.author))?;
+            writeln!(out, r#"        <category term="{}" label="{}"/>"#, escape(&metadata.category), escape(&category))?;
+            if !item.post.excerpt().is_empty() {
+                writeln!(out, "        <summary>{}</summary>", escape(item.post.excerpt()))?;
+            }
+            writeln!(out, r#"        <content type="html">{}</content>"#, escape(&content))?;
+            writeln!(out, "    </entry>")?;
+        }
+
+        writeln!(out, "</feed>")
+    }
+
+    fn render_json(feed: Feed, context: &mut dyn RenderContext, out: &mut dyn Write) -> std::io::Result<()> {
+        let self_url = context.resolve_url(&Link::ID(context.current_page().0.to_string()));
+
+        let mut items = Vec::new();
+        for item in &feed.items {
+            let metadata = &item.post.metadata;
+            let url = context.resolve_url(&item.link);
+            let category = context.resolve_category(&metadata.category).title.clone();
+            let content = item.content(context)?;
+            let mut json_item = json!({
+                "id": url.0,
+                "url": url.0,
+                "title": metadata.title,
+                "content_html": content,
+                "date_published": metadata.date.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "authors": [{ "name": metadata.author }],
+                "tags": [category]
+            });
+            if !item.post.excerpt().is_empty() {
+                json_item["summary"] = json!(item.post.excerpt());
+            }
+            items.push(json_item);
+        }
+
+        let json_feed = json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": feed.title,
+            "home_page_url": context.resolve_url(&feed.link).0,
+            "feed_url": self_url.0,
+            "description": feed.description,
+            "language": "en",
+            "items": items
+        });
+
+        serde_json::to_writer_pretty(&mut *out, &json_feed)?;
+        writeln!(out)
+    }
+}
+
+impl Renderable for FeedRender {
+    fn render(self: Box<Self>, context: &mut dyn RenderContext, out: &mut dyn Write) -> std::io::Result<()> {
+        match self.format {
+            FeedFormat::Rss => FeedRender::render_rss(self.feed, context, out),
+            FeedFormat::Atom => FeedRender::render_atom(self.feed, context, out),
+            FeedFormat::Json => FeedRender::render_json(self.feed, context, out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{absolute_links, absolute_url};
+
+    const PAGE: &str = "https://example.com/blog/tech/post.html";
+
+    #[test]
+    fn absolute() {
+        assert_eq!(absolute_url("https://other.org/a.png", PAGE), "https://other.org/a.png");
+        assert_eq!(absolute_url("//cdn.example.com/a.png", PAGE), "https://cdn.example.com/a.png");
+    }
+
+    #[test]
+    fn root_relative() {
+        assert_eq!(absolute_url("/index.html", PAGE), "https://example.com/index.html");
+        assert_eq!(absolute_url("/rsc/a.png", "https://example.com"), "https://example.com/rsc/a.png");
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(absolute_url("other.html", PAGE), "https://example.com/blog/tech/other.html");
+        assert_eq!(absolute_url("./other.html", PAGE), "https://example.com/blog/tech/other.html");
+        assert_eq!(absolute_url("../../rsc/a.png", PAGE), "https://example.com/rsc/a.png");
+        assert_eq!(absolute_url("other.html?page=2#top", PAGE), "https://example.com/blog/tech/other.html?page=2#top");
+        assert_eq!(absolute_url("../index.html", "/blog/tech/post.html"), "/blog/index.html");
+    }
+
+    #[test]
+    fn fragment_and_query() {
+        assert_eq!(absolute_url("#section", PAGE), "https://example.com/blog/tech/post.html#section");
+        assert_eq!(absolute_url("#other", "https://example.com/post.html#section"), "https://example.com/post.html#other");
+        assert_eq!(absolute_url("?page=2", "https://example.com/post.html?page=1"), "https://example.com/post.html?page=2");
+    }
+
+    #[test]
+    fn other_schemes() {
+        assert_eq!(absolute_url("mailto:me@example.com", PAGE), "mailto:me@example.com");
+        assert_eq!(absolute_url("data:image/png;base64,AAAA", PAGE), "data:image/png;base64,AAAA");
+        assert_eq!(absolute_url("tel:+15550100", PAGE), "tel:+15550100");
+    }
+
+    #[test]
+    fn links_in_html() {
+        assert_eq!(
+            absolute_links(r##"<a href="../index.html">x</a><img src="data:image/png;base64,AAAA"><a href="#note">y</a>"##, PAGE),
+            r##"<a href="https://example.com/blog/index.html">x</a><img src="data:image/png;base64,AAAA"><a href="https://example.com/blog/tech/post.html#note">y</a>"##
+        );
+    }
+}
@@ -0,0 +1,420 @@
+//! Build-time syntax highlighting of code blocks
+//!
+//! A small tokeniser per language rather than a full grammar; it only needs to be good enough for the snippets in posts
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    String,
+    Number,
+    Comment,
+    /// `true`, `false`, `null` and the like
+    Literal,
+    /// Object keys in JSON and YAML
+    Key,
+    /// Shell variables and Rust lifetimes
+    Variable,
+    /// Rust macro invocations
+    Macro,
+    /// Rust attributes
+    Attribute,
+}
+
+impl TokenKind {
+    pub fn class(self) -> &'static str {
+        match self {
+            TokenKind::Keyword => "highlight_keyword",
+            TokenKind::Type => "highlight_type",
+            TokenKind::String => "highlight_string",
+            TokenKind::Number => "highlight_number",
+            TokenKind::Comment => "highlight_comment",
+            TokenKind::Literal => "highlight_literal",
+            TokenKind::Key => "highlight_key",
+            TokenKind::Variable => "highlight_variable",
+            TokenKind::Macro => "highlight_macro",
+            TokenKind::Attribute => "highlight_attribute"
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Dialect {
+    Rust,
+    Json,
+    Yaml,
+    Shell,
+    JavaScript,
+    Sql,
+}
+
+struct Language {
+    dialect: Dialect,
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+impl Language {
+    /// Language for a code block's language tag, `None` if it is not highlighted
+    fn from_name(name: &str) -> Option<Language> {
+        Some(match &*name.to_ascii_lowercase() {
+            "rust" | "rs" => Language {
+                dialect: Dialect::Rust,
+                keywords: &[
+                    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "fn", "for", "if", "impl", "in",
+                    "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+                    "type", "unsafe", "use", "where", "while", "yield"
+                ],
+                types: &[
+                    "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64"
+                ],
+                literals: &["true", "false"],
+                line_comments: &["//"],
+                block_comment: Some(("/*", "*/")),
+                quotes: &['"'],
+            },
+            "json" => Language {
+                dialect: Dialect::Json,
+                keywords: &[],
+                types: &[],
+                literals: &["true", "false", "null"],
+                line_comments: &[],
+                block_comment: None,
+                quotes: &['"'],
+            },
+            "yaml" | "yml" => Language {
+                dialect: Dialect::Yaml,
+                keywords: &[],
+                types: &[],
+                literals: &["true", "false", "True", "False", "TRUE", "FALSE", "null", "Null", "NULL", "yes", "no", "~"],
+                line_comments: &["#"],
+                block_comment: None,
+                quotes: &['"', '\''],
+            },
+            "sh" | "shell" | "bash" | "zsh" | "console" => Language {
+                dialect: Dialect::Shell,
+                keywords: &[
+                    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac", "in", "function", "return",
+                    "export", "local", "readonly"
+                ],
+                types: &[],
+                literals: &["true", "false"],
+                line_comments: &["#"],
+                block_comment: None,
+                quotes: &['"', '\''],
+            },
+            "js" | "javascript" | "mjs" | "ts" | "typescript" => Language {
+                dialect: Dialect::JavaScript,
+                keywords: &[
+                    "async", "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete", "do", "else",
+                    "export", "extends", "finally", "for", "from", "function", "if", "import", "in", "instanceof", "let", "new", "of",
+                    "return", "static", "super", "switch", "this", "throw", "try", "typeof", "var", "void", "while", "with", "yield"
+                ],
+                types: &["number", "string", "boolean", "any", "unknown", "never", "object"],
+                literals: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+                line_comments: &["//"],
+                block_comment: Some(("/*", "*/")),
+                quotes: &['"', '\'', '`'],
+            },
+            "sql" => Language {
+                dialect: Dialect::Sql,
+                keywords: &[
+                    "select", "from", "where", "and", "or", "not", "insert", "into", "values", "update", "set", "delete", "create", "table",
+                    "drop", "alter", "index", "on", "join", "left", "right", "inner", "outer", "full", "cross", "as", "group", "by", "order",
+                    "having", "limit", "offset", "distinct", "union", "all", "exists", "in", "is", "like", "between", "case", "when", "then",
+                    "else", "end", "primary", "key", "foreign", "references", "default", "unique", "with", "returning", "asc", "desc",
+                    "begin", "commit", "rollback", "if", "view", "count", "sum", "avg", "min", "max"
+                ],
+                types: &[
+                    "int", "integer", "bigint", "smallint", "text", "varchar", "char", "boolean", "bool", "date", "time", "timestamp",
+                    "numeric", "decimal", "real", "float", "double", "serial", "blob"
+                ],
+                literals: &["null", "true", "false"],
+                line_comments: &["--"],
+                block_comment: Some(("/*", "*/")),
+                quotes: &['\''],
+            },
+            _ => return None
+        })
+    }
+
+    fn is_word(&self, list: &[&str], word: &str) -> bool {
+        if self.dialect == Dialect::Sql {
+            list.iter().any(|entry| entry.eq_ignore_ascii_case(word))
+        } else {
+            list.contains(&word)
+        }
+    }
+
+    /// Whether a backslash escapes the next character in strings quoted by `quote`
+    fn backslash_escapes(&self, quote: char) -> bool {
+        !(quote == '\'' && matches!(self.dialect, Dialect::Shell | Dialect::Yaml | Dialect::Sql))
+    }
+}
+
+fn is_identifier_start(char: char) -> bool {
+    char.is_alphabetic() || char == '_'
+}
+
+fn is_identifier_char(char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+/// Length of the identifier at the start of `text`
+fn identifier_length(text: &str) -> usize {
+    text.find(|char: char| !is_identifier_char(char)).unwrap_or(text.len())
+}
+
+/// Length of the string quoted by `quote` at the start of `text`, up to the end of `text` if it is unterminated
+fn quoted_length(text: &str, quote: char, escapes: bool) -> usize {
+    let mut chars = text.char_indices().skip(1);
+    while let Some((index, char)) = chars.next() {
+        if char == '\\' && escapes {
+            chars.next();
+        } else if char == quote {
+            return index + char.len_utf8();
+        }
+    }
+    text.len()
+}
+
+/// Length of a Rust raw, byte or byte raw string such as `r#"..."#` at the start of `text`
+fn rust_prefixed_string_length(text: &str) -> Option<usize> {
+    let unprefixed = text.strip_prefix('b').unwrap_or(text);
+    if let Some(raw) = unprefixed.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        if !raw[hashes..].starts_with('"') {
+            return None;
+        }
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let content_start = text.len() - raw.len() + hashes + 1;
+        Some(text[content_start..].find(&terminator).map_or(text.len(), |end| content_start + end + terminator.len()))
+    } else if unprefixed.len() < text.len() && (unprefixed.starts_with('"') || unprefixed.starts_with('\'')) {
+        let quote = unprefixed.chars().next().unwrap();
+        Some(1 + quoted_length(unprefixed, quote, true))
+    } else {
+        None
+    }
+}
+
+/// Length of the YAML mapping key at the start of `line`, excluding the `:`
+fn yaml_key_length(line: &str) -> Option<usize> {
+    let line = line.split('\n').next().unwrap_or(line);
+    let mut search = 0;
+    while let Some(colon) = line[search..].find(':') {
+        let colon = search + colon;
+        let after = &line[colon + 1..];
+        if after.is_empty() || after.starts_with([' ', '\t', '\r']) {
+            let key = &line[..colon];
+            return if key.trim().is_empty() || key.contains(" #") || key.starts_with(['[', '{', '#']) {
+                None
+            } else {
+                Some(colon)
+            };
+        }
+        search = colon + 1;
+    }
+    None
+}
+
+/// Splits `code` into tokens for `language`, returning `None` if the language is not supported
+///
+/// Text that is not part of a highlighted token is returned with no kind
+pub fn highlight<'a>(language: &str, code: &'a str) -> Option<Vec<(Option<TokenKind>, &'a str)>> {
+    let language = Language::from_name(language)?;
+    let mut tokens = Vec::new();
+    let mut plain_start = 0;
+    let mut position = 0;
+    // Whether only indentation (and YAML sequence dashes) precede `position` on its line
+    let mut line_start = true;
+
+    let push = |tokens: &mut Vec<(Option<TokenKind>, &'a str)>, plain_start: &mut usize, kind: TokenKind, start: usize, end: usize| {
+        if *plain_start < start {
+            tokens.push((None, &code[*plain_start..start]));
+        }
+        tokens.push((Some(kind), &code[start..end]));
+        *plain_start = end;
+    };
+
+    while position < code.len() {
+        let rest = &code[position..];
+        let char = rest.chars().next().unwrap();
+        let previous = code[..position].chars().next_back();
+
+        let comment_allowed = !matches!(language.dialect, Dialect::Shell | Dialect::Yaml) || previous.map_or(true, char::is_whitespace);
+        let token = if comment_allowed && language.line_comments.iter().any(|prefix| rest.starts_with(prefix)) {
+            Some((TokenKind::Comment, rest.find('\n').unwrap_or(rest.len())))
+        } else if let Some((open, close)) = language.block_comment && rest.starts_with(open) {
+            Some((TokenKind::Comment, rest[open.len()..].find(close).map_or(rest.len(), |end| open.len() + end + close.len())))
+        } else if language.dialect == Dialect::Yaml && line_start && !char.is_whitespace() && !rest.starts_with("- ") && let Some(key) = yaml_key_length(rest) {
+            Some((TokenKind::Key, key))
+        } else if language.dialect == Dialect::Rust && (rest.starts_with("#[") || rest.starts_with("#![")) {
+            let mut depth = 0;
+            let end = rest.char_indices()
+                .find(|(_, char)| {
+                    match char {
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        _ => {}
+                    }
+                    *char == ']' && depth == 0
+                })
+                .map_or(rest.len(), |(index, _)| index + 1);
+            Some((TokenKind::Attribute, end))
+        } else if language.dialect == Dialect::Rust && char == '\'' {
+            let mut chars = rest.chars().skip(1);
+            match (chars.next(), chars.next()) {
+                (Some('\\'), _) | (Some(_), Some('\'')) => Some((TokenKind::String, quoted_length(rest, '\'', true))),
+                (Some(char), _) if is_identifier_start(char) => Some((TokenKind::Variable, 1 + identifier_length(&rest[1..]))),
+                _ => None
+            }
+        } else if language.dialect == Dialect::Rust && matches!(char, 'r' | 'b') && let Some(length) = rust_prefixed_string_length(rest) {
+            Some((TokenKind::String, length))
+        } else if language.dialect == Dialect::Shell && char == '$' {
+            match rest[1..].chars().next() {
+                Some('{') => Some((TokenKind::Variable, rest.find('}').map_or(rest.len(), |end| end + 1))),
+                Some(char) if is_identifier_start(char) => Some((TokenKind::Variable, 1 + identifier_length(&rest[1..]))),
+                Some(char) if char.is_ascii_digit() || "@*#?$!-".contains(char) => Some((TokenKind::Variable, 1 + char.len_utf8())),
+                _ => None
+            }
+        } else if language.quotes.contains(&char) {
+            let length = quoted_length(rest, char, language.backslash_escapes(char));
+            if language.dialect == Dialect::Json && rest[length..].trim_start().starts_with(':') {
+                Some((TokenKind::Key, length))
+            } else {
+                Some((TokenKind::String, length))
+            }
+        } else if char.is_ascii_digit() && !previous.is_some_and(is_identifier_char) {
+            let mut length = 0;
+            for (index, char) in rest.char_indices() {
+                let decimal_point = char == '.' && rest[index + 1..].starts_with(|char: char| char.is_ascii_digit());
+                if !(is_identifier_char(char) || decimal_point) {
+                    break;
+                }
+                length = index + char.len_utf8();
+            }
+            Some((TokenKind::Number, length))
+        } else if is_identifier_start(char) && !previous.is_some_and(is_identifier_char) {
+            let length = identifier_length(rest);
+            let word = &rest[..length];
+            if language.dialect == Dialect::Rust && rest[length..].starts_with('!') && !rest[length..].starts_with("!=") {
+                Some((TokenKind::Macro, length + 1))
+            } else if language.is_word(language.keywords, word) {
+                Some((TokenKind::Keyword, length))
+            } else if language.is_word(language.literals, word) {
+                Some((TokenKind::Literal, length))
+            } else if language.is_word(language.types, word) || (language.dialect == Dialect::Rust && word.starts_with(|char: char| char.is_uppercase())) {
+                Some((TokenKind::Type, length))
+            } else {
+                // Skip the whole word, so that words such as `u8x` are not split into highlighted parts
+                position += length;
+                line_start = false;
+                continue;
+            }
+        } else {
+            None
+        };
+
+        match token {
+            Some((kind, length)) => {
+                push(&mut tokens, &mut plain_start, kind, position, position + length);
+                position += length;
+                line_start = false;
+            }
+            None => {
+                position += char.len_utf8();
+                line_start = match char {
+                    '\n' => true,
+                    ' ' | '\t' => line_start,
+                    '-' if language.dialect == Dialect::Yaml => line_start && code[position..].starts_with([' ', '\n']),
+                    _ => false
+                };
+            }
+        }
+    }
+    if plain_start < code.len() {
+        tokens.push((None, &code[plain_start..]));
+    }
+    Some(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{highlight, TokenKind};
+    use super::TokenKind::*;
+
+    /// Highlighted tokens of `code`, leaving out plain text
+    fn tokens<'a>(language: &str, code: &'a str) -> Vec<(TokenKind, &'a str)> {
+        highlight(language, code).unwrap().into_iter()
+            .filter_map(|(kind, text)| Some((kind?, text)))
+            .collect()
+    }
+
+    #[test]
+    fn unsupported_language() {
+        assert_eq!(highlight("brainfuck", "+[-]"), None);
+    }
+
+    #[test]
+    fn plain_text_is_kept() {
+        let code = "let x = 1; // one";
+        let text = highlight("rust", code).unwrap().into_iter().map(|(_, text)| text).collect::<Vec<_>>().concat();
+        assert_eq!(text, code);
+    }
+
+    #[test]
+    fn rust() {
+        assert_eq!(tokens("rust", r#"let s = "a \"quoted\" word";"#), vec![(Keyword, "let"), (String, r#""a \"quoted\" word""#)]);
+        assert_eq!(tokens("rust", r###"r#"raw "string""# b'x' '\n'"###), vec![(String, r###"r#"raw "string""#"###), (String, "b'x'"), (String, r"'\n'")]);
+        assert_eq!(tokens("rust", "x // line\n/* block */ y"), vec![(Comment, "// line"), (Comment, "/* block */")]);
+        assert_eq!(tokens("rust", "1 2.5 0xff 1_000u32 x1"), vec![(Number, "1"), (Number, "2.5"), (Number, "0xff"), (Number, "1_000u32")]);
+        assert_eq!(tokens("rust", "fn f<'a>(x: &'a u8) -> Option<u8>"), vec![
+            (Keyword, "fn"), (Variable, "'a"), (Variable, "'a"), (Type, "u8"), (Type, "Option"), (Type, "u8")
+        ]);
+        assert_eq!(tokens("rust", "#[derive(Debug)] println!(\"{}\", a != b)"), vec![(Attribute, "#[derive(Debug)]"), (Macro, "println!"), (String, "\"{}\"")]);
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(tokens("json", r#"{"key": "va\"lue", "n": -1.5e3, "ok": null}"#), vec![
+            (Key, r#""key""#), (String, r#""va\"lue""#), (Key, r#""n""#), (Number, "1.5e3"), (Key, r#""ok""#), (Literal, "null")
+        ]);
+    }
+
+    #[test]
+    fn yaml() {
+        assert_eq!(tokens("yaml", "key: 'it''s' # comment\n- item: \"a\\\"b\"\nurl: http://x#y"), vec![
+            (Key, "key"), (String, "'it'"), (String, "'s'"), (Comment, "# comment"), (Key, "item"), (String, "\"a\\\"b\""), (Key, "url")
+        ]);
+        assert_eq!(tokens("yaml", "port: 8080\nenabled: true"), vec![(Key, "port"), (Number, "8080"), (Key, "enabled"), (Literal, "true")]);
+    }
+
+    #[test]
+    fn shell() {
+        assert_eq!(tokens("sh", r#"echo "$HOME \"x\"" 'no \' # comment"#), vec![(String, r#""$HOME \"x\"""#), (String, r"'no \'"), (Comment, "# comment")]);
+        assert_eq!(tokens("bash", "if [ $1 -gt 10 ]; then echo ${NAME}#x; fi"), vec![
+            (Keyword, "if"), (Variable, "$1"), (Number, "10"), (Keyword, "then"), (Variable, "${NAME}"), (Keyword, "fi")
+        ]);
+    }
+
+    #[test]
+    fn javascript() {
+        assert_eq!(tokens("js", r#"const s = `a ${b}` + 'c\'d'; // done"#), vec![
+            (Keyword, "const"), (String, "`a ${b}`"), (String, r"'c\'d'"), (Comment, "// done")
+        ]);
+        assert_eq!(tokens("js", "let n = 3.14 /* pi */ ?? null"), vec![(Keyword, "let"), (Number, "3.14"), (Comment, "/* pi */"), (Literal, "null")]);
+    }
+
+    #[test]
+    fn sql() {
+        assert_eq!(tokens("sql", "SELECT 'it''s', 42 FROM t -- comment\n/* block */ where x IS NULL"), vec![
+            (Keyword, "SELECT"), (String, "'it'"), (String, "'s'"), (Number, "42"), (Keyword, "FROM"), (Comment, "-- comment"),
+            (Comment, "/* block */"), (Keyword, "where"), (Keyword, "IS"), (Literal, "NULL")
+        ]);
+        assert_eq!(tokens("sql", r"SELECT 'a\'"), vec![(Keyword, "SELECT"), (String, r"'a\'")]);
+    }
+}
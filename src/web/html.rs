@@ -625,6 +625,16 @@ impl Html for RawHtml {
     }
 }
 
+/// Escapes text for use in HTML or XML content and attribute values
+pub fn escape(text: &str) -> String {
+    // Replacing these in one pass would be a lot more efficient, but :effort:
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
 #[derive(Debug)]
 pub struct HtmlPlaintext(pub String);
 
@@ -634,13 +644,7 @@ impl Html for HtmlPlaintext {
     }
 
     fn build(self, _context: &mut dyn RenderContext, html_out: &mut dyn Write, format: HtmlFormat) -> std::io::Result<()> {
-        // Replacing these in one pass would be a lot more efficient, but :effort:
-        let text = self.0.replace("\r\n", "\n")
-            .replace('&', "&amp;")
-            .replace('<', "&lt;")
-            .replace('>', "&gt;")
-            .replace('"', "&quot;")
-            .replace('\'', "&#x27;");
+        let text = escape(&self.0.replace("\r\n", "\n"));
 
         if let HtmlFormat::Indent(indent) = format {
             text.lines()
@@ -0,0 +1,817 @@
+//! Conversion of TeX math to MathML, so that equations render without client-side JavaScript
+//!
+//! Supports the subset of TeX commonly used in posts; scripts, fractions, roots, greek letters and symbols, large operators, functions, accents, fonts,
+//! `\left`/`\right` delimiters and the `matrix`, `cases` and `aligned` environments
+
+use crate::web::html::escape;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Font {
+    Roman,
+    Bold,
+    Italic,
+    DoubleStruck,
+    Script,
+    Fraktur,
+    SansSerif,
+    Monospace,
+}
+
+impl Font {
+    /// Maps a letter or digit to its Mathematical Alphanumeric Symbol, as MathML Core only supports `mathvariant="normal"`
+    fn apply(self, char: char) -> char {
+        let exception = match (self, char) {
+            (Font::Italic, 'h') => Some('\u{210E}'),
+            (Font::DoubleStruck, 'C') => Some('\u{2102}'),
+            (Font::DoubleStruck, 'H') => Some('\u{210D}'),
+            (Font::DoubleStruck, 'N') => Some('\u{2115}'),
+            (Font::DoubleStruck, 'P') => Some('\u{2119}'),
+            (Font::DoubleStruck, 'Q') => Some('\u{211A}'),
+            (Font::DoubleStruck, 'R') => Some('\u{211D}'),
+            (Font::DoubleStruck, 'Z') => Some('\u{2124}'),
+            (Font::Script, 'B') => Some('\u{212C}'),
+            (Font::Script, 'E') => Some('\u{2130}'),
+            (Font::Script, 'F') => Some('\u{2131}'),
+            (Font::Script, 'H') => Some('\u{210B}'),
+            (Font::Script, 'I') => Some('\u{2110}'),
+            (Font::Script, 'L') => Some('\u{2112}'),
+            (Font::Script, 'M') => Some('\u{2133}'),
+            (Font::Script, 'R') => Some('\u{211B}'),
+            (Font::Script, 'e') => Some('\u{212F}'),
+            (Font::Script, 'g') => Some('\u{210A}'),
+            (Font::Script, 'o') => Some('\u{2134}'),
+            (Font::Fraktur, 'C') => Some('\u{212D}'),
+            (Font::Fraktur, 'H') => Some('\u{210C}'),
+            (Font::Fraktur, 'I') => Some('\u{2111}'),
+            (Font::Fraktur, 'R') => Some('\u{211C}'),
+            (Font::Fraktur, 'Z') => Some('\u{2128}'),
+            _ => None
+        };
+        if let Some(exception) = exception {
+            return exception;
+        }
+
+        let (upper, lower, digit) = match self {
+            Font::Roman => return char,
+            Font::Bold => (0x1D400, Some(0x1D41A), Some(0x1D7CE)),
+            Font::Italic => (0x1D434, Some(0x1D44E), None),
+            Font::DoubleStruck => (0x1D538, Some(0x1D552), Some(0x1D7D8)),
+            Font::Script => (0x1D49C, Some(0x1D4B6), None),
+            Font::Fraktur => (0x1D504, Some(0x1D51E), None),
+            Font::SansSerif => (0x1D5A0, Some(0x1D5BA), Some(0x1D7E2)),
+            Font::Monospace => (0x1D670, Some(0x1D68A), Some(0x1D7F6)),
+        };
+        let code_point = match char {
+            'A'..='Z' => Some(upper + (char as u32 - 'A' as u32)),
+            'a'..='z' => lower.map(|lower| lower + (char as u32 - 'a' as u32)),
+            '0'..='9' => digit.map(|digit| digit + (char as u32 - '0' as u32)),
+            _ => None
+        };
+        code_point.and_then(char::from_u32).unwrap_or(char)
+    }
+}
+
+enum Symbol {
+    Identifier(&'static str),
+    /// Identifier rendered upright, such as upper-case greek letters
+    UprightIdentifier(&'static str),
+    Operator(&'static str),
+    /// Large operator, with `limits` placing scripts under and over it in display math
+    LargeOperator { symbol: &'static str, limits: bool },
+    /// Named function such as `sin`, with `limits` as for large operators
+    Function { limits: bool },
+}
+
+fn symbol(name: &str) -> Option<Symbol> {
+    use Symbol::*;
+    Some(match name {
+        "alpha" => Identifier("α"),
+        "beta" => Identifier("β"),
+        "gamma" => Identifier("γ"),
+        "delta" => Identifier("δ"),
+        "epsilon" => Identifier("ϵ"),
+        "varepsilon" => Identifier("ε"),
+        "zeta" => Identifier("ζ"),
+        "eta" => Identifier("η"),
+        "theta" => Identifier("θ"),
+        "vartheta" => Identifier("ϑ"),
+        "iota" => Identifier("ι"),
+        "kappa" => Identifier("κ"),
+        "lambda" => Identifier("λ"),
+        "mu" => Identifier("μ"),
+        "nu" => Identifier("ν"),
+        "xi" => Identifier("ξ"),
+        "pi" => Identifier("π"),
+        "varpi" => Identifier("ϖ"),
+        "rho" => Identifier("ρ"),
+        "varrho" => Identifier("ϱ"),
+        "sigma" => Identifier("σ"),
+        "varsigma" => Identifier("ς"),
+        "tau" => Identifier("τ"),
+        "upsilon" => Identifier("υ"),
+        "phi" => Identifier("ϕ"),
+        "varphi" => Identifier("φ"),
+        "chi" => Identifier("χ"),
+        "psi" => Identifier("ψ"),
+        "omega" => Identifier("ω"),
+        "Gamma" => UprightIdentifier("Γ"),
+        "Delta" => UprightIdentifier("Δ"),
+        "Theta" => UprightIdentifier("Θ"),
+        "Lambda" => UprightIdentifier("Λ"),
+        "Xi" => UprightIdentifier("Ξ"),
+        "Pi" => UprightIdentifier("Π"),
+        "Sigma" => UprightIdentifier("Σ"),
+        "Upsilon" => UprightIdentifier("Υ"),
+        "Phi" => UprightIdentifier("Φ"),
+        "Psi" => UprightIdentifier("Ψ"),
+        "Omega" => UprightIdentifier("Ω"),
+
+        "infty" => Identifier("∞"),
+        "partial" => Identifier("∂"),
+        "nabla" => Identifier("∇"),
+        "ell" => Identifier("ℓ"),
+        "hbar" => Identifier("ℏ"),
+        "emptyset" | "varnothing" => Identifier("∅"),
+        "aleph" => Identifier("ℵ"),
+        "Re" => Identifier("ℜ"),
+        "Im" => Identifier("ℑ"),
+
+        "pm" => Operator("±"),
+        "mp" => Operator("∓"),
+        "times" => Operator("×"),
+        "div" => Operator("÷"),
+        "cdot" => Operator("⋅"),
+        "ast" => Operator("∗"),
+        "star" => Operator("⋆"),
+        "circ" => Operator("∘"),
+        "bullet" => Operator("∙"),
+        "oplus" => Operator("⊕"),
+        "otimes" => Operator("⊗"),
+        "cap" => Operator("∩"),
+        "cup" => Operator("∪"),
+        "wedge" | "land" => Operator("∧"),
+        "vee" | "lor" => Operator("∨"),
+        "setminus" => Operator("∖"),
+        "leq" | "le" => Operator("≤"),
+        "geq" | "ge" => Operator("≥"),
+        "neq" | "ne" => Operator("≠"),
+        "approx" => Operator("≈"),
+        "equiv" => Operator("≡"),
+        "sim" => Operator("∼"),
+        "simeq" => Operator("≃"),
+        "cong" => Operator("≅"),
+        "propto" => Operator("∝"),
+        "ll" => Operator("≪"),
+        "gg" => Operator("≫"),
+        "subset" => Operator("⊂"),
+        "supset" => Operator("⊃"),
+        "subseteq" => Operator("⊆"),
+        "supseteq" => Operator("⊇"),
+        "in" => Operator("∈"),
+        "notin" => Operator("∉"),
+        "ni" => Operator("∋"),
+        "mid" => Operator("∣"),
+        "parallel" => Operator("∥"),
+        "perp" => Operator("⊥"),
+        "to" | "rightarrow" => Operator("→"),
+        "gets" | "leftarrow" => Operator("←"),
+        "leftrightarrow" => Operator("↔"),
+        "Rightarrow" => Operator("⇒"),
+        "Leftarrow" => Operator("⇐"),
+        "Leftrightarrow" => Operator("⇔"),
+        "implies" => Operator("⟹"),
+        "iff" => Operator("⟺"),
+        "mapsto" => Operator("↦"),
+        "uparrow" => Operator("↑"),
+        "downarrow" => Operator("↓"),
+        "longrightarrow" => Operator("⟶"),
+        "longleftarrow" => Operator("⟵"),
+        "forall" => Operator("∀"),
+        "exists" => Operator("∃"),
+        "neg" | "lnot" => Operator("¬"),
+        "ldots" | "dots" => Operator("…"),
+        "cdots" => Operator("⋯"),
+        "vdots" => Operator("⋮"),
+        "ddots" => Operator("⋱"),
+        "langle" => Operator("⟨"),
+        "rangle" => Operator("⟩"),
+        "lfloor" => Operator("⌊"),
+        "rfloor" => Operator("⌋"),
+        "lceil" => Operator("⌈"),
+        "rceil" => Operator("⌉"),
+        "lbrace" => Operator("{"),
+        "rbrace" => Operator("}"),
+        "vert" => Operator("|"),
+        "Vert" => Operator("‖"),
+        "colon" => Operator(":"),
+        "prime" => Operator("′"),
+
+        "sum" => LargeOperator { symbol: "∑", limits: true },
+        "prod" => LargeOperator { symbol: "∏", limits: true },
+        "coprod" => LargeOperator { symbol: "∐", limits: true },
+        "bigcup" => LargeOperator { symbol: "⋃", limits: true },
+        "bigcap" => LargeOperator { symbol: "⋂", limits: true },
+        "bigvee" => LargeOperator { symbol: "⋁", limits: true },
+        "bigwedge" => LargeOperator { symbol: "⋀", limits: true },
+        "bigoplus" => LargeOperator { symbol: "⨁", limits: true },
+        "bigotimes" => LargeOperator { symbol: "⨂", limits: true },
+        "int" => LargeOperator { symbol: "∫", limits: false },
+        "iint" => LargeOperator { symbol: "∬", limits: false },
+        "iiint" => LargeOperator { symbol: "∭", limits: false },
+        "oint" => LargeOperator { symbol: "∮", limits: false },
+
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh" | "cosh" | "tanh" | "coth"
+        | "log" | "ln" | "lg" | "exp" | "det" | "dim" | "ker" | "deg" | "arg" | "hom" | "gcd" | "Pr" => Function { limits: false },
+        "lim" | "max" | "min" | "sup" | "inf" | "limsup" | "liminf" => Function { limits: true },
+        _ => return None
+    })
+}
+
+/// Accent placed over (or under) its argument, and whether it stretches to the argument's width
+fn accent(name: &str) -> Option<(&'static str, bool, bool)> {
+    // (accent, stretchy, under)
+    Some(match name {
+        "hat" => ("^", false, false),
+        "widehat" => ("^", true, false),
+        "bar" => ("\u{AF}", false, false),
+        "overline" => ("\u{203E}", true, false),
+        "underline" => ("_", true, true),
+        "vec" => ("→", false, false),
+        "overrightarrow" => ("→", true, false),
+        "dot" => ("\u{2D9}", false, false),
+        "ddot" => ("\u{A8}", false, false),
+        "tilde" => ("~", false, false),
+        "widetilde" => ("~", true, false),
+        "overbrace" => ("⏞", true, false),
+        "underbrace" => ("⏟", true, true),
+        _ => return None
+    })
+}
+
+fn mrow(items: Vec<String>) -> String {
+    if items.len() == 1 {
+        items.into_iter().next().unwrap()
+    } else {
+        format!("<mrow>{}</mrow>", items.concat())
+    }
+}
+
+fn mo(operator: &str) -> String {
+    format!("<mo>{}</mo>", escape(operator))
+}
+
+fn fence(delimiter: Option<&str>) -> String {
+    match delimiter {
+        Some(delimiter) => format!(r#"<mo fence="true" stretchy="true">{}</mo>"#, escape(delimiter)),
+        None => String::new()
+    }
+}
+
+fn space(width: &str) -> String {
+    format!(r#"<mspace width="{}"/>"#, width)
+}
+
+/// Where a row of math is being parsed, determining which tokens end it
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Scope {
+    Top,
+    Group,
+    Left,
+    Table,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    display: bool,
+    font: Option<Font>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let char = self.peek();
+        self.position += 1;
+        char
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    /// Name of the command at the current position, without consuming it
+    fn peek_command(&self) -> Option<String> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+        match self.peek_at(1) {
+            Some(char) if char.is_ascii_alphabetic() => Some(
+                self.chars[self.position + 1..].iter()
+                    .take_while(|char| char.is_ascii_alphabetic())
+                    .collect()
+            ),
+            Some(char) => Some(char.to_string()),
+            None => None
+        }
+    }
+
+    fn read_command(&mut self) -> Result<String, String> {
+        let command = self.peek_command().ok_or("expected a command after `\\`")?;
+        self.position += 1 + command.chars().count();
+        Ok(command)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(char) if char == expected => Ok(()),
+            Some(char) => Err(format!("expected `{}`, found `{}`", expected, char)),
+            None => Err(format!("expected `{}`, found end of math", expected))
+        }
+    }
+
+    /// Reads the raw text of a braced argument, for commands taking text rather than math
+    fn read_text_argument(&mut self) -> Result<String, String> {
+        self.expect('{')?;
+        let mut depth = 0;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('{') => depth += 1,
+                Some('}') if depth == 0 => return Ok(text),
+                Some('}') => depth -= 1,
+                Some('\\') if matches!(self.peek(), Some('{' | '}')) => {
+                    text.push(self.next().unwrap());
+                    continue;
+                }
+                Some(_) => {}
+                None => return Err("missing `}`".to_string())
+            }
+            text.push(self.chars[self.position - 1]);
+        }
+    }
+
+    /// Parses a sub-expression with the same settings as this parser
+    fn parse_nested(&self, tex: &str) -> Result<String, String> {
+        let mut parser = Parser { chars: tex.chars().collect(), position: 0, display: self.display, font: self.font };
+        Ok(mrow(parser.parse_row(Scope::Top)?))
+    }
+
+    fn parse_row(&mut self, scope: Scope) -> Result<Vec<String>, String> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let command = self.peek_command();
+            match (self.peek(), command.as_deref()) {
+                (None, _) => return match scope {
+                    Scope::Top => Ok(items),
+                    Scope::Group => Err("missing `}`".to_string()),
+                    Scope::Left => Err("missing `\\right`".to_string()),
+                    Scope::Table => Err("missing `\\end`".to_string())
+                },
+                (Some('}'), _) if scope == Scope::Group => return Ok(items),
+                (Some('&'), _) | (_, Some("\\") | Some("end")) if scope == Scope::Table => return Ok(items),
+                (_, Some("right")) if scope == Scope::Left => return Ok(items),
+                (Some('}'), _) => return Err("unexpected `}`".to_string()),
+                (Some('&'), _) => return Err("`&` outside of an environment".to_string()),
+                (_, Some("\\")) => return Err("line break `\\\\` outside of an environment".to_string()),
+                (_, Some("right")) => return Err("`\\right` without `\\left`".to_string()),
+                (_, Some("end")) => return Err("`\\end` without `\\begin`".to_string()),
+                _ => items.push(self.parse_scripted()?)
+            }
+        }
+    }
+
+    /// Parses an atom along with any sub- and superscripts
+    fn parse_scripted(&mut self) -> Result<String, String> {
+        let (base, limits) = self.parse_atom()?;
+        let (mut subscript, mut superscript) = (None, None);
+        let mut primes = 0;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('\'') => {
+                    self.position += 1;
+                    primes += 1;
+                }
+                Some('^') if superscript.is_none() => {
+                    self.position += 1;
+                    superscript = Some(self.parse_argument()?);
+                }
+                Some('_') if subscript.is_none() => {
+                    self.position += 1;
+                    subscript = Some(self.parse_argument()?);
+                }
+                Some('^') => return Err("double superscript".to_string()),
+                Some('_') => return Err("double subscript".to_string()),
+                _ => break
+            }
+        }
+        if primes > 0 {
+            let prime = mo(&"′".repeat(primes));
+            superscript = Some(match superscript {
+                Some(superscript) => mrow(vec![prime, superscript]),
+                None => prime
+            });
+        }
+
+        let under_over = limits && self.display;
+        Ok(match (subscript, superscript) {
+            (None, None) => base,
+            (Some(subscript), None) if under_over => format!("<munder>{}{}</munder>", base, subscript),
+            (Some(subscript), None) => format!("<msub>{}{}</msub>", base, subscript),
+            (None, Some(superscript)) if under_over => format!("<mover>{}{}</mover>", base, superscript),
+            (None, Some(superscript)) => format!("<msup>{}{}</msup>", base, superscript),
+            (Some(subscript), Some(superscript)) if under_over => format!("<munderover>{}{}{}</munderover>", base, subscript, superscript),
+            (Some(subscript), Some(superscript)) => format!("<msubsup>{}{}{}</msubsup>", base, subscript, superscript)
+        })
+    }
+
+    /// Parses the argument of a command or script, either a braced group or a single token
+    fn parse_argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            // A single digit, `x^23` is `x^2` followed by `3`
+            Some(char) if char.is_ascii_digit() => {
+                self.position += 1;
+                Ok(self.number(char.to_string()))
+            }
+            Some(_) => Ok(self.parse_atom()?.0),
+            None => Err("missing argument".to_string())
+        }
+    }
+
+    fn identifier(&self, text: &str) -> String {
+        match self.font {
+            Some(Font::Roman) => format!(r#"<mi mathvariant="normal">{}</mi>"#, escape(text)),
+            Some(font) => format!("<mi>{}</mi>", escape(&text.chars().map(|char| font.apply(char)).collect::<String>())),
+            None => format!("<mi>{}</mi>", escape(text))
+        }
+    }
+
+    fn number(&self, digits: String) -> String {
+        match self.font {
+            Some(font) => format!("<mn>{}</mn>", digits.chars().map(|char| font.apply(char)).collect::<String>()),
+            None => format!("<mn>{}</mn>", digits)
+        }
+    }
+
+    /// Parses a single atom, returning its MathML and whether scripts are placed as limits in display math
+    fn parse_atom(&mut self) -> Result<(String, bool), String> {
+        self.skip_whitespace();
+        let Some(char) = self.peek() else {
+            return Err("expected math, found end of math".to_string());
+        };
+        match char {
+            '{' => {
+                self.position += 1;
+                let items = self.parse_row(Scope::Group)?;
+                self.expect('}')?;
+                Ok((mrow(items), false))
+            }
+            '\\' => self.parse_command(),
+            // Scripts without a base
+            '^' | '_' => Ok(("<mrow></mrow>".to_string(), false)),
+            '0'..='9' | '.' if char != '.' || self.peek_at(1).is_some_and(|char| char.is_ascii_digit()) => {
+                let mut digits = String::new();
+                while let Some(char) = self.peek() && (char.is_ascii_digit() || (char == '.' && self.peek_at(1).is_some_and(|char| char.is_ascii_digit()))) {
+                    digits.push(char);
+                    self.position += 1;
+                }
+                Ok((self.number(digits), false))
+            }
+            '~' => {
+                self.position += 1;
+                Ok((space("0.25em"), false))
+            }
+            char => {
+                self.position += 1;
+                if char.is_alphabetic() {
+                    Ok((self.identifier(&char.to_string()), false))
+                } else if char.is_numeric() {
+                    Ok((self.number(char.to_string()), false))
+                } else {
+                    Ok((mo(match char {
+                        '-' => "\u{2212}",
+                        '*' => "\u{2217}",
+                        _ => return Ok((mo(&char.to_string()), false))
+                    }), false))
+                }
+            }
+        }
+    }
+
+    fn read_delimiter(&mut self) -> Result<Option<&'static str>, String> {
+        self.skip_whitespace();
+        if self.peek() == Some('\\') {
+            let command = self.read_command()?;
+            return Ok(Some(match command.as_str() {
+                "{" | "lbrace" => "{",
+                "}" | "rbrace" => "}",
+                "|" | "Vert" => "‖",
+                "vert" => "|",
+                "langle" => "⟨",
+                "rangle" => "⟩",
+                "lfloor" => "⌊",
+                "rfloor" => "⌋",
+                "lceil" => "⌈",
+                "rceil" => "⌉",
+                _ => return Err(format!("`\\{}` is not a delimiter", command))
+            }));
+        }
+        match self.next() {
+            Some('.') => Ok(None),
+            Some('(') => Ok(Some("(")),
+            Some(')') => Ok(Some(")")),
+            Some('[') => Ok(Some("[")),
+            Some(']') => Ok(Some("]")),
+            Some('|') => Ok(Some("|")),
+            Some('/') => Ok(Some("/")),
+            Some(char) => Err(format!("`{}` is not a delimiter", char)),
+            None => Err("missing delimiter".to_string())
+        }
+    }
+
+    /// Parses the argument of a font command in `font`
+    fn parse_in_font(&mut self, font: Font) -> Result<String, String> {
+        let outer_font = self.font.replace(font);
+        let argument = self.parse_argument();
+        self.font = outer_font;
+        argument
+    }
+
+    fn parse_command(&mut self) -> Result<(String, bool), String> {
+        let command = self.read_command()?;
+        if let Some(symbol) = symbol(&command) {
+            return Ok(match symbol {
+                Symbol::Identifier(identifier) => (self.identifier(identifier), false),
+                Symbol::UprightIdentifier(identifier) => (format!(r#"<mi mathvariant="normal">{}</mi>"#, identifier), false),
+                Symbol::Operator(operator) => (mo(operator), false),
+                Symbol::LargeOperator { symbol, limits } => (format!(r#"<mo largeop="true" movablelimits="{}">{}</mo>"#, limits, symbol), limits),
+                Symbol::Function { limits } => {
+                    let name = match command.as_str() {
+                        "limsup" => "lim sup",
+                        "liminf" => "lim inf",
+                        name => name
+                    };
+                    (format!("<mi>{}</mi>", name), limits)
+                }
+            });
+        }
+        if let Some((accent, stretchy, under)) = accent(&command) {
+            let argument = self.parse_argument()?;
+            let accent = format!(r#"<mo stretchy="{}">{}</mo>"#, stretchy, escape(accent));
+            // Braces take scripts as limits, e.g. `\underbrace{x + y}_{n}`
+            let is_brace = command.ends_with("brace");
+            return Ok(if under {
+                (format!(r#"<munder accentunder="true">{}{}</munder>"#, argument, accent), is_brace)
+            } else {
+                (format!(r#"<mover accent="true">{}{}</mover>"#, argument, accent), is_brace)
+            });
+        }
+
+        let math = match command.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.parse_argument()?;
+                let denominator = self.parse_argument()?;
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" => {
+                let top = self.parse_argument()?;
+                let bottom = self.parse_argument()?;
+                format!(r#"<mrow>{}<mfrac linethickness="0">{}{}</mfrac>{}</mrow>"#, fence(Some("(")), top, bottom, fence(Some(")")))
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    self.position += 1;
+                    let start = self.position;
+                    while self.peek().is_some_and(|char| char != ']') {
+                        self.position += 1;
+                    }
+                    let index = self.chars[start..self.position].iter().collect::<String>();
+                    self.expect(']')?;
+                    let index = self.parse_nested(&index)?;
+                    let radicand = self.parse_argument()?;
+                    format!("<mroot>{}{}</mroot>", radicand, index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.parse_argument()?)
+                }
+            }
+            "left" => {
+                let open = self.read_delimiter()?;
+                let content = self.parse_row(Scope::Left)?;
+                self.read_command()?; // \right
+                let close = self.read_delimiter()?;
+                format!("<mrow>{}{}{}</mrow>", fence(open), content.concat(), fence(close))
+            }
+            "big" | "bigl" | "bigr" | "Big" | "Bigl" | "Bigr" | "bigg" | "biggl" | "biggr" | "Bigg" | "Biggl" | "Biggr" => {
+                let size = match command.trim_end_matches(['l', 'r']) {
+                    "big" => "1.2em",
+                    "Big" => "1.623em",
+                    "bigg" => "2.047em",
+                    _ => "2.470em"
+                };
+                match self.read_delimiter()? {
+                    Some(delimiter) => format!(r#"<mo maxsize="{size}" minsize="{size}">{}</mo>"#, escape(delimiter), size = size),
+                    None => String::new()
+                }
+            }
+            "mathrm" => self.parse_in_font(Font::Roman)?,
+            "mathbf" | "boldsymbol" => self.parse_in_font(Font::Bold)?,
+            "mathit" => self.parse_in_font(Font::Italic)?,
+            "mathbb" => self.parse_in_font(Font::DoubleStruck)?,
+            "mathcal" | "mathscr" => self.parse_in_font(Font::Script)?,
+            "mathfrak" => self.parse_in_font(Font::Fraktur)?,
+            "mathsf" => self.parse_in_font(Font::SansSerif)?,
+            "mathtt" => self.parse_in_font(Font::Monospace)?,
+            "text" | "textrm" | "mbox" => format!("<mtext>{}</mtext>", escape(&self.read_text_argument()?)),
+            "operatorname" => format!("<mi>{}</mi>", escape(&self.read_text_argument()?)),
+            "begin" => self.parse_environment()?,
+            "," => space("0.1667em"),
+            ":" | ">" => space("0.2222em"),
+            ";" => space("0.2778em"),
+            "!" => space("-0.1667em"),
+            " " => space("0.25em"),
+            "quad" => space("1em"),
+            "qquad" => space("2em"),
+            "{" | "}" | "|" | "%" | "$" | "#" | "&" | "_" => mo(if command == "|" { "‖" } else { &command }),
+            _ => return Err(format!("unsupported TeX command `\\{}`", command))
+        };
+        Ok((math, false))
+    }
+
+    fn parse_environment(&mut self) -> Result<String, String> {
+        let name = self.read_text_argument()?;
+        // Delimiters, and column alignment by column index
+        let (open, close, alignment): (Option<&str>, Option<&str>, fn(usize) -> Option<&'static str>) = match name.as_str() {
+            "matrix" => (None, None, |_| None),
+            "pmatrix" => (Some("("), Some(")"), |_| None),
+            "bmatrix" => (Some("["), Some("]"), |_| None),
+            "Bmatrix" => (Some("{"), Some("}"), |_| None),
+            "vmatrix" => (Some("|"), Some("|"), |_| None),
+            "Vmatrix" => (Some("‖"), Some("‖"), |_| None),
+            "cases" => (Some("{"), None, |_| Some("math_align-left")),
+            "aligned" | "align" | "align*" | "split" => (None, None, |column| Some(if column % 2 == 0 { "math_align-right" } else { "math_align-left" })),
+            "gathered" | "gather" | "gather*" => (None, None, |_| None),
+            _ => return Err(format!("unsupported environment `{}`", name))
+        };
+
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        loop {
+            row.push(mrow(self.parse_row(Scope::Table)?));
+            if self.peek() == Some('&') {
+                self.position += 1;
+            } else if self.read_command()? == "\\" {
+                rows.push(std::mem::take(&mut row));
+            } else {
+                let end = self.read_text_argument()?;
+                if end != name {
+                    return Err(format!("`\\begin{{{}}}` ended by `\\end{{{}}}`", name, end));
+                }
+                // Ignore the empty row after a trailing `\\`
+                if !(row.len() == 1 && row[0] == "<mrow></mrow>" && rows.len() > 0) {
+                    rows.push(row);
+                }
+                break;
+            }
+        }
+
+        let table = rows.into_iter()
+            .map(|row| {
+                let cells = row.into_iter()
+                    .enumerate()
+                    .map(|(column, cell)| match alignment(column) {
+                        Some(class) => format!(r#"<mtd class="{}">{}</mtd>"#, class, cell),
+                        None => format!("<mtd>{}</mtd>", cell)
+                    })
+                    .collect::<String>();
+                format!("<mtr>{}</mtr>", cells)
+            })
+            .collect::<String>();
+        Ok(format!("<mrow>{}<mtable>{}</mtable>{}</mrow>", fence(open), table, fence(close)))
+    }
+}
+
+/// Converts TeX math to a MathML `<math>` element, as a block if `display` is set
+pub fn tex_to_mathml(tex: &str, display: bool) -> Result<String, String> {
+    let mut parser = Parser { chars: tex.chars().collect(), position: 0, display, font: None };
+    let row = mrow(parser.parse_row(Scope::Top)?);
+    Ok(format!(
+        r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="{}"><semantics>{}<annotation encoding="application/x-tex">{}</annotation></semantics></math>"#,
+        if display { "block" } else { "inline" },
+        row,
+        escape(tex.trim())
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tex_to_mathml;
+
+    /// MathML of `tex` without the surrounding `<math>` element and TeX annotation
+    fn mathml(tex: &str) -> String {
+        let math = tex_to_mathml(tex, false).unwrap();
+        let start = math.find("<semantics>").unwrap() + "<semantics>".len();
+        let end = math.find("<annotation").unwrap();
+        math[start..end].to_string()
+    }
+
+    fn error(tex: &str) -> String {
+        tex_to_mathml(tex, false).unwrap_err()
+    }
+
+    #[test]
+    fn math_element() {
+        assert_eq!(
+            tex_to_mathml("a < b", true).unwrap(),
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="block"><semantics><mrow><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow><annotation encoding="application/x-tex">a &lt; b</annotation></semantics></math>"#
+        );
+        assert!(tex_to_mathml("x", false).unwrap().contains(r#"display="inline""#));
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(mathml("x^2"), "<msup><mi>x</mi><mn>2</mn></msup>");
+        assert_eq!(mathml("x_i"), "<msub><mi>x</mi><mi>i</mi></msub>");
+        assert_eq!(mathml("x_i^2"), "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>");
+        assert_eq!(mathml("x^{n+1}"), "<msup><mi>x</mi><mrow><mi>n</mi><mo>+</mo><mn>1</mn></mrow></msup>");
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(mathml(r"\frac{a}{b}"), "<mfrac><mi>a</mi><mi>b</mi></mfrac>");
+        assert_eq!(mathml(r"\frac12"), "<mfrac><mn>1</mn><mn>2</mn></mfrac>");
+    }
+
+    #[test]
+    fn delimiters() {
+        assert_eq!(
+            mathml(r"\left( x \right)"),
+            r#"<mrow><mo fence="true" stretchy="true">(</mo><mi>x</mi><mo fence="true" stretchy="true">)</mo></mrow>"#
+        );
+        assert_eq!(mathml(r"\left. x \right|"), r#"<mrow><mi>x</mi><mo fence="true" stretchy="true">|</mo></mrow>"#);
+    }
+
+    #[test]
+    fn environments() {
+        assert_eq!(
+            mathml(r"\begin{matrix} a & b \\ c & d \end{matrix}"),
+            "<mrow><mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable></mrow>"
+        );
+        assert_eq!(
+            mathml(r"\begin{cases} 1 & x > 0 \\ 0 & \text{otherwise} \end{cases}"),
+            concat!(
+                r#"<mrow><mo fence="true" stretchy="true">{</mo><mtable>"#,
+                r#"<mtr><mtd class="math_align-left"><mn>1</mn></mtd><mtd class="math_align-left"><mrow><mi>x</mi><mo>&gt;</mo><mn>0</mn></mrow></mtd></mtr>"#,
+                r#"<mtr><mtd class="math_align-left"><mn>0</mn></mtd><mtd class="math_align-left"><mtext>otherwise</mtext></mtd></mtr>"#,
+                r#"</mtable></mrow>"#
+            )
+        );
+        assert_eq!(
+            mathml(r"\begin{aligned} a &= b \end{aligned}"),
+            r#"<mrow><mtable><mtr><mtd class="math_align-right"><mi>a</mi></mtd><mtd class="math_align-left"><mrow><mo>=</mo><mi>b</mi></mrow></mtd></mtr></mtable></mrow>"#
+        );
+    }
+
+    #[test]
+    fn malformed_scripts() {
+        assert_eq!(error("x^2^3"), "double superscript");
+        assert_eq!(error("x_1_2"), "double subscript");
+        assert_eq!(error("x^"), "missing argument");
+        assert_eq!(error(r"\frac{a}"), "missing argument");
+    }
+
+    #[test]
+    fn malformed_groups() {
+        assert_eq!(error("{x"), "missing `}`");
+        assert_eq!(error("x}"), "unexpected `}`");
+        assert_eq!(error(r"\left( x"), r"missing `\right`");
+        assert_eq!(error(r"x \right)"), r"`\right` without `\left`");
+        assert_eq!(error(r"\left x \right)"), "`x` is not a delimiter");
+    }
+
+    #[test]
+    fn malformed_environments() {
+        assert_eq!(error(r"\begin{matrix} x"), r"missing `\end`");
+        assert_eq!(error(r"\begin{matrix} x \end{cases}"), r"`\begin{matrix}` ended by `\end{cases}`");
+        assert_eq!(error(r"\begin{foo} x \end{foo}"), "unsupported environment `foo`");
+        assert_eq!(error("a & b"), "`&` outside of an environment");
+        assert_eq!(error(r"a \\ b"), r"line break `\\` outside of an environment");
+    }
+
+    #[test]
+    fn unsupported_command() {
+        assert_eq!(error(r"\foo"), r"unsupported TeX command `\foo`");
+    }
+}
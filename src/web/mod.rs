@@ -1,8 +1,10 @@
 use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use crate::web::css::CSSBuilder;
+use crate::diagnostics::Diagnostic;
+use crate::web::feed::FeedFormat;
 use crate::web::html::{Html, HtmlFormat};
 use crate::website::Category;
 
@@ -12,10 +14,16 @@ pub mod css;
 #[macro_use]
 pub mod html;
 
-pub mod feed {}
+pub mod feed;
+
+pub mod sitemap;
 
 pub mod component;
 
+pub mod math;
+
+pub mod highlight;
+
 pub trait Renderable {
     fn render(self: Box<Self>, context: &mut dyn RenderContext, out: &mut dyn Write) -> std::io::Result<()>;
 }
@@ -71,11 +79,21 @@ pub trait RenderContext {
     fn title(&self) -> &str;
     fn title_prefix(&self) -> Option<&str>;
     fn resolve_href(&self, link: &Link, from_page: PageRef) -> HRef;
+    fn resolve_url(&self, link: &Link) -> HRef;
     fn resolve_link_title(&self, link: &Link) -> String;
     fn resolve_link(&self, link: &Link, from_page: PageRef) -> (String, HRef);
     fn resolve_category(&self, category_id: &str) -> &Category;
     fn current_page(&self) -> PageRef;
+    fn set_current_page(&mut self, page_id: &str);
     fn stylesheet(&mut self) -> &mut CSSBuilder;
     fn stylesheet_link(&self, for_page: PageRef) -> HRef;
     fn global_scripts(&self, for_page: PageRef) -> Vec<HRef>;
+    fn feeds(&self, for_page: PageRef) -> Vec<(String, FeedFormat, HRef)>;
+    /// Endpoint pages listen on for reload events, if pages are built for the local preview server
+    fn live_reload(&self) -> Option<HRef>;
+    /// Hosts external images may be embedded from, `None` if images from any host are allowed
+    fn image_hosts(&self) -> Option<&[String]>;
+    /// Directory code block `include=` paths are relative to
+    fn snippet_root(&self) -> &Path;
+    fn report(&self, diagnostic: Diagnostic);
 }
\ No newline at end of file
@@ -0,0 +1,28 @@
+use std::io::Write;
+use chrono::{DateTime, SecondsFormat, Utc};
+use crate::web::{Link, Renderable, RenderContext};
+use crate::web::html::escape;
+
+#[derive(Debug, Clone)]
+pub struct SitemapEntry {
+    pub(crate) link: Link,
+    pub(crate) last_modified: Option<DateTime<Utc>>,
+}
+
+pub struct SitemapRender(pub Vec<SitemapEntry>);
+
+impl Renderable for SitemapRender {
+    fn render(self: Box<Self>, context: &mut dyn RenderContext, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#)?;
+        for entry in self.0 {
+            writeln!(out, "    <url>")?;
+            writeln!(out, "        <loc>{}</loc>", escape(&context.resolve_url(&entry.link).0))?;
+            if let Some(last_modified) = entry.last_modified {
+                writeln!(out, "        <lastmod>{}</lastmod>", last_modified.to_rfc3339_opts(SecondsFormat::Secs, true))?;
+            }
+            writeln!(out, "    </url>")?;
+        }
+        writeln!(out, "</urlset>")
+    }
+}
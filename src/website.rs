@@ -1,12 +1,19 @@
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use chrono::{DateTime};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use crate::blog_post::{BlogPost, Published};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::util;
 use crate::util::{Language};
-use crate::web::component::{content_bottom_spacer, content_column, contentbox, html_heading, html_text, navigation_menu, NavigationItem, page, postlist, PostListEntry, title};
+use crate::web::component::{content_bottom_spacer, content_column, contentbox, draft_banner, html_heading, html_text, navigation_menu, NavigationItem, page, postlist, PostListEntry, title};
 use crate::web::{HRef, Link, PageRef, Renderable, RenderContext, ResourceRender, SpecialCaseRender};
+use crate::web::feed::{Feed, FeedFormat, FeedItem, FeedRender};
+use crate::web::sitemap::{SitemapEntry, SitemapRender};
 use crate::web::css::CSSBuilder;
 use crate::web::html::{Html};
 use crate::website_resource::{Resource};
@@ -29,6 +36,15 @@ impl Category {
         )
     }
 
+    /// Unlisted categories within this category, along with every subcategory of an unlisted category
+    fn iter_unlisted(&self) -> Box<dyn Iterator<Item=&Category> + '_> {
+        if self.unlisted {
+            self.iter_recurse()
+        } else {
+            Box::new(self.sub_categories.iter().flat_map(Category::iter_unlisted))
+        }
+    }
+
     fn load_map(&self, category_map: &mut HashMap<String, Vec<String>>, path: &mut Vec<String>) -> Result<(), String> {
         path.push(self.id_string.clone());
         category_map.insert(
@@ -56,6 +72,7 @@ pub enum Document {
     HTML(HtmlDocument),
     Feed(FeedDocument),
     Css(CSSDocument),
+    Sitemap(SitemapDocument),
     Resource(ResourceDocument),
 }
 
@@ -65,6 +82,7 @@ impl Document {
             Document::HTML(HtmlDocument { id, .. }) => &*id,
             Document::Feed(FeedDocument { id, .. }) => &*id,
             Document::Css(CSSDocument { id, .. }) => &*id,
+            Document::Sitemap(SitemapDocument { id, .. }) => &*id,
             Document::Resource(ResourceDocument { resource, .. }) => &*resource.id
         }
     }
@@ -72,8 +90,9 @@ impl Document {
     pub fn title(&self) -> Option<&str> {
         match self {
             Document::HTML(html) => Some(&*html.title),
-            Document::Feed(_) => None,
+            Document::Feed(feed) => Some(&*feed.title),
             Document::Css(_) => None,
+            Document::Sitemap(_) => None,
             Document::Resource(_) => None
         }
     }
@@ -83,6 +102,7 @@ impl Document {
             Document::HTML(HtmlDocument { filename, .. }) => *filename,
             Document::Feed(FeedDocument { filename, .. }) => *filename,
             Document::Css(CSSDocument { filename, .. }) => *filename,
+            Document::Sitemap(SitemapDocument { filename, .. }) => *filename,
             Document::Resource(ResourceDocument { filename, .. }) => *filename
         }
     }
@@ -90,8 +110,9 @@ impl Document {
     pub fn extension(&self) -> &str {
         match self {
             Document::HTML(_) => ".html",
-            Document::Feed(_) => ".rss",
+            Document::Feed(feed) => feed.format.extension(),
             Document::Css(_) => ".css",
+            Document::Sitemap(_) => ".xml",
             Document::Resource(doc) => doc.resource.resource_type.extension()
         }
     }
@@ -101,6 +122,7 @@ impl Document {
             Document::HTML(HtmlDocument { category, .. }) => category.as_deref(),
             Document::Feed(FeedDocument { category, .. }) => category.as_deref(),
             Document::Css(_) => None,
+            Document::Sitemap(_) => None,
             Document::Resource(_) => None
         }
     }
@@ -116,6 +138,9 @@ impl Document {
             Document::Css(mut css) => css.render.take()
                 .expect("double-render")
                 .call_once((context, &css)),
+            Document::Sitemap(mut sitemap) => sitemap.render.take()
+                .expect("double-render")
+                .call_once((context, &sitemap)),
             Document::Resource(mut script) => script.render.take()
                 .expect("double-render")
                 .call_once((context, &script))
@@ -141,6 +166,12 @@ impl From<CSSDocument> for Document {
     }
 }
 
+impl From<SitemapDocument> for Document {
+    fn from(value: SitemapDocument) -> Self {
+        Document::Sitemap(value)
+    }
+}
+
 impl From<ResourceDocument> for Document {
     fn from(value: ResourceDocument) -> Self {
         Document::Resource(value)
@@ -152,7 +183,7 @@ pub struct HtmlDocument {
     title: String,
     filename: FileName,
     category: Option<String>,
-    render: Option<Box<dyn FnOnce(&dyn RenderContext, &HtmlDocument) -> Box<dyn Renderable>>>,
+    render: Option<Box<dyn FnOnce(&dyn RenderContext, &HtmlDocument) -> Box<dyn Renderable> + Send>>,
 }
 
 impl HtmlDocument {
@@ -160,7 +191,7 @@ impl HtmlDocument {
         PageRef(&*self.id)
     }
 
-    pub fn new<R: FnOnce(&dyn RenderContext, &HtmlDocument) -> Box<dyn Renderable> + 'static>(id: String, title: String, filename: FileName, category: Option<String>, render: R) -> Self {
+    pub fn new<R: FnOnce(&dyn RenderContext, &HtmlDocument) -> Box<dyn Renderable> + Send + 'static>(id: String, title: String, filename: FileName, category: Option<String>, render: R) -> Self {
         Self { id, title, filename, category, render: Some(Box::new(render)) }
     }
 }
@@ -179,14 +210,15 @@ impl Debug for HtmlDocument {
 pub struct FeedDocument {
     id: String,
     title: String,
+    format: FeedFormat,
     filename: FileName,
     category: Option<String>,
-    render: Option<Box<dyn FnOnce(&dyn RenderContext, &FeedDocument) -> Box<dyn Renderable>>>,
+    render: Option<Box<dyn FnOnce(&dyn RenderContext, &FeedDocument) -> Box<dyn Renderable> + Send>>,
 }
 
 impl FeedDocument {
-    pub fn new<R: FnOnce(&dyn RenderContext, &FeedDocument) -> Box<dyn Renderable> + 'static>(id: String, title: String, filename: FileName, category: Option<String>, render: R) -> Self {
-        Self { id, title, filename, category, render: Some(Box::new(render)) }
+    pub fn new<R: FnOnce(&dyn RenderContext, &FeedDocument) -> Box<dyn Renderable> + Send + 'static>(id: String, title: String, format: FeedFormat, filename: FileName, category: Option<String>, render: R) -> Self {
+        Self { id, title, format, filename, category, render: Some(Box::new(render)) }
     }
 }
 
@@ -194,6 +226,7 @@ impl Debug for FeedDocument {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("FeedDocument")
             .field("id", &self.id)
+            .field("format", &self.format)
             .field("category", &self.category)
             .field("filename", &self.filename)
             .finish()
@@ -203,11 +236,11 @@ impl Debug for FeedDocument {
 pub struct CSSDocument {
     id: String,
     filename: FileName,
-    render: Option<Box<dyn FnOnce(&dyn RenderContext, &CSSDocument) -> Box<dyn Renderable>>>,
+    render: Option<Box<dyn FnOnce(&dyn RenderContext, &CSSDocument) -> Box<dyn Renderable> + Send>>,
 }
 
 impl CSSDocument {
-    pub fn new<R: FnOnce(&dyn RenderContext, &CSSDocument) -> Box<dyn Renderable> + 'static>(id: String, filename: FileName, render: R) -> Self {
+    pub fn new<R: FnOnce(&dyn RenderContext, &CSSDocument) -> Box<dyn Renderable> + Send + 'static>(id: String, filename: FileName, render: R) -> Self {
         Self { id, filename, render: Some(Box::new(render)) }
     }
 }
@@ -221,14 +254,35 @@ impl Debug for CSSDocument {
     }
 }
 
+pub struct SitemapDocument {
+    id: String,
+    filename: FileName,
+    render: Option<Box<dyn FnOnce(&dyn RenderContext, &SitemapDocument) -> Box<dyn Renderable> + Send>>,
+}
+
+impl SitemapDocument {
+    pub fn new<R: FnOnce(&dyn RenderContext, &SitemapDocument) -> Box<dyn Renderable> + Send + 'static>(id: String, filename: FileName, render: R) -> Self {
+        Self { id, filename, render: Some(Box::new(render)) }
+    }
+}
+
+impl Debug for SitemapDocument {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SitemapDocument")
+            .field("id", &self.id)
+            .field("filename", &self.filename)
+            .finish()
+    }
+}
+
 pub struct ResourceDocument {
     resource: Resource,
     filename: FileName,
-    render: Option<Box<dyn FnOnce(&dyn RenderContext, &ResourceDocument) -> Box<dyn Renderable>>>,
+    render: Option<Box<dyn FnOnce(&dyn RenderContext, &ResourceDocument) -> Box<dyn Renderable> + Send>>,
 }
 
 impl ResourceDocument {
-    pub fn new<R: FnOnce(&dyn RenderContext, &ResourceDocument) -> Box<dyn Renderable> + 'static>(resource: Resource, filename: FileName, render: R) -> Self {
+    pub fn new<R: FnOnce(&dyn RenderContext, &ResourceDocument) -> Box<dyn Renderable> + Send + 'static>(resource: Resource, filename: FileName, render: R) -> Self {
         Self { resource, filename, render: Some(Box::new(render)) }
     }
 }
@@ -252,24 +306,91 @@ impl Document {
 pub struct Website {
     pub title: String,
     pub description: String,
+    pub base_url: String,
     pub categories: Vec<Category>,
+    /// Hosts external images may be embedded from, images from any host are allowed if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_hosts: Option<Vec<String>>,
     #[serde(skip, default = "IndexMap::new")]
     pub posts: IndexMap<String, BlogPost>,
     #[serde(skip, default = "Vec::new")]
     pub resources: Vec<Resource>,
+    #[serde(skip)]
+    pub live_reload: bool,
+    #[serde(skip)]
+    pub snippet_root: PathBuf,
 }
 
 impl Website {
-    fn validate(&self) {
+    fn validate(&mut self, diagnostics: &mut Diagnostics) {
+        let host = self.base_url.strip_prefix("https://").or_else(|| self.base_url.strip_prefix("http://"));
+        if !host.is_some_and(|host| !host.is_empty() && !host.starts_with('/')) {
+            diagnostics.push(Diagnostic::error(format!("base_url `{}` is not an absolute http or https URL", self.base_url)));
+        }
+
         for category in &self.categories {
             for subcategory in &category.sub_categories {
                 for sub_sub_category in &subcategory.sub_categories {
                     if sub_sub_category.sub_categories.len() > 0 {
-                        panic!("Category {} nests beyond level 3", sub_sub_category.id_string);
+                        diagnostics.push(Diagnostic::error(format!("category `{}` nests beyond level 3", sub_sub_category.id_string)));
                     }
                 }
             }
         }
+
+        let category_ids = self.categories.iter()
+            .flat_map(Category::iter_recurse)
+            .map(|category| category.id_string.as_str())
+            .collect::<HashSet<_>>();
+        self.posts.retain(|_, post| {
+            if category_ids.contains(post.metadata.category.as_str()) {
+                true
+            } else {
+                diagnostics.push(Diagnostic::error(format!("post has unknown category `{}`", post.metadata.category)).source(&post.source));
+                false
+            }
+        });
+    }
+
+    fn validate_links(&self, routes: &HashMap<String, Vec<String>>, diagnostics: &mut Diagnostics) {
+        let mut heading_ids = HashMap::new();
+        let mut check_link = |target: &str| -> Option<String> {
+            let (id, fragment) = split_fragment(target);
+            if !routes.contains_key(id) {
+                return Some(match id.strip_prefix("resource:") {
+                    Some(resource) => format!("unknown resource `{}`", resource),
+                    None => format!("link to unknown page `{}`", id)
+                });
+            }
+            let fragment = fragment?;
+            let Some(post) = self.posts.get(id) else {
+                return Some(format!("link to section `{}` of `{}`, which is not a post", fragment, id));
+            };
+            let post_headings = heading_ids.entry(id.to_string()).or_insert_with(|| post.heading_ids());
+            (!post_headings.contains(fragment)).then(|| format!("link to unknown section `{}` of `{}`", fragment, id))
+        };
+
+        for item in self.navigation() {
+            let links = match item {
+                NavigationItem::SingleLink(link) => vec![link],
+                NavigationItem::Tree(link, tree) => std::iter::once(link)
+                    .chain(tree.into_iter().flat_map(|(link, sub_links)| std::iter::once(link).chain(sub_links)))
+                    .collect()
+            };
+            for link in links {
+                if let Link::ID(id) = link && let Some(message) = check_link(&id) {
+                    diagnostics.push(Diagnostic::error(format!("navigation {}", message)));
+                }
+            }
+        }
+
+        for post in self.posts.values() {
+            for (target, position) in post.links() {
+                if let Some(message) = check_link(&target) {
+                    diagnostics.push(Diagnostic::error(message).source(&post.source).position(position.as_ref()));
+                }
+            }
+        }
     }
 
     pub fn nav_items(&self) -> Vec<Link> {
@@ -280,14 +401,19 @@ impl Website {
         items
     }
 
-    fn render_page<C: Html + 'static>(context: &dyn RenderContext, document: &HtmlDocument, navigation: Vec<NavigationItem>, content: C) -> Box<dyn Renderable> {
+    fn render_page<C: Html + 'static>(context: &dyn RenderContext, document: &HtmlDocument, navigation: Vec<NavigationItem>, draft: bool, content: C) -> Box<dyn Renderable> {
         let mut content_items: Vec<Box<dyn Html>> = vec![Box::new(title(context.title().to_string()))];
         content_items.push(Box::new(navigation_menu(navigation)));
+        if draft {
+            content_items.push(Box::new(draft_banner()));
+        }
         content_items.push(Box::new(content));
         content_items.push(Box::new(content_bottom_spacer()));
         Box::new(page(
             context.stylesheet_link(PageRef(&*document.id)),
             context.global_scripts(document.page_ref()),
+            context.feeds(document.page_ref()),
+            context.live_reload(),
             &Language::English,
             {
                 if let Some(title_prefix) = context.title_prefix() {
@@ -301,6 +427,26 @@ impl Website {
         ))
     }
 
+    fn navigation(&self) -> Vec<NavigationItem> {
+        let mut navigation = Vec::new();
+        navigation.push(NavigationItem::SingleLink(Link::ID("home".to_string())));
+        for category in &self.categories {
+            let mut tree = Vec::new();
+            for head_category in category.sub_categories.iter().filter(|c| !c.unlisted) {
+                tree.push((
+                    Link::ID(head_category.id_string.clone()),
+                    head_category.sub_categories.iter().map(|category| Link::ID(category.id_string.clone())).collect()
+                ))
+            }
+            if tree.len() > 0 {
+                navigation.push(NavigationItem::Tree(Link::ID(category.id_string.clone()), tree))
+            } else {
+                navigation.push(NavigationItem::SingleLink(Link::ID(category.id_string.clone())))
+            }
+        }
+        navigation
+    }
+
     fn documents(&self) -> Vec<Document> {
         let mut documents: Vec<Document> = Vec::new();
 
@@ -339,23 +485,7 @@ impl Website {
             load_category_children(&mut category_children, category, None)
         }
 
-        let mut navigation = Vec::new();
-        navigation.push(NavigationItem::SingleLink(Link::ID("home".to_string())));
-        for category in &self.categories {
-            let mut tree = Vec::new();
-            for head_category in category.sub_categories.iter().filter(|c| !c.unlisted) {
-                tree.push((
-                    Link::ID(head_category.id_string.clone()),
-                    head_category.sub_categories.iter().map(|category| Link::ID(category.id_string.clone())).collect()
-                ))
-            }
-            if tree.len() > 0 {
-                navigation.push(NavigationItem::Tree(Link::ID(category.id_string.clone()), tree))
-            } else {
-                navigation.push(NavigationItem::SingleLink(Link::ID(category.id_string.clone())))
-            }
-        }
-
+        let navigation = self.navigation();
 
         let home_nav = navigation.clone();
         let description = self.description.clone();
@@ -366,7 +496,7 @@ impl Website {
                 FileName::Index,
                 None,
                 move |ctx, document| {
-                    Website::render_page(ctx, document, home_nav, contentbox(html_text(description)))
+                    Website::render_page(ctx, document, home_nav, false, contentbox(html_text(description)))
                 },
             ).into()
         );
@@ -388,30 +518,57 @@ impl Website {
             )
         }
 
+        fn feed_items<'a>(posts: impl Iterator<Item=(&'a String, &'a BlogPost)>) -> Vec<FeedItem> {
+            posts.filter(|(_, post)| post.metadata.published == Published::True)
+                .map(|(id, post)| FeedItem { link: Link::ID(id.clone()), post: post.clone() })
+                .collect()
+        }
+
+        fn push_feeds(documents: &mut Vec<Document>, id: String, category: Option<String>, feed: Feed) {
+            for format in FeedFormat::ALL {
+                let feed = feed.clone();
+                documents.push(
+                    FeedDocument::new(
+                        format!("{}:{}", id, format.name()),
+                        feed.title.clone(),
+                        format,
+                        FileName::Custom("feed"),
+                        category.clone(),
+                        move |_, _| Box::new(FeedRender { format, feed }),
+                    ).into()
+                );
+            }
+        }
+
+        push_feeds(&mut documents, "feed".to_string(), None, Feed {
+            title: self.title.clone(),
+            description: self.description.clone(),
+            link: Link::ID("home".to_string()),
+            items: feed_items(self.posts.iter()),
+        });
+
         for category in self.categories.iter().flat_map(Category::iter_recurse).filter(|category| !category.unlisted) {
             let category_nav = navigation.clone();
+            let title = category.title.clone();
             let description = category.description.clone();
 
-            let mut content: Vec<Box<dyn Html>> = vec![
-                Box::new(html_heading(1, html_text(&category.title))),
-                Box::new(html_text(description)),
-            ];
-
+            let mut post_list = None;
             if category_children.get("blog").unwrap().contains(&category.id_string) {
                 let post_categories = category_children.get(&category.id_string).unwrap();
-                let list: Vec<PostListEntry> = self.posts.iter()
-                    .filter(|(_, post)| post_categories.contains(&post.metadata.category))
-                    .filter(|(_, post)| post.metadata.published == Published::True) // Ignore unpublished or unlisted posts
-                    .map(|(id, post)| {
-                        PostListEntry {
-                            post_id: id,
-                            post_date: &post.metadata.date,
-                            post_title: &post.metadata.title,
-                        }
-                    })
-                    .collect::<Vec<_>>();
-
-                content.push(Box::new(postlist(list)))
+                post_list = Some(
+                    self.posts.iter()
+                        .filter(|(_, post)| post_categories.contains(&post.metadata.category))
+                        .filter(|(_, post)| post.metadata.published == Published::True) // Ignore unpublished or unlisted posts
+                        .map(|(id, post)| (id.clone(), post.metadata.date, post.metadata.title.clone(), post.excerpt().to_string()))
+                        .collect::<Vec<_>>()
+                );
+
+                push_feeds(&mut documents, format!("feed:{}", category.id_string), Some(category.id_string.clone()), Feed {
+                    title: format!("{} - {}", self.title, category.title),
+                    description: category.description.clone(),
+                    link: Link::ID(category.id_string.clone()),
+                    items: feed_items(self.posts.iter().filter(|(_, post)| post_categories.contains(&post.metadata.category))),
+                });
             }
 
             documents.push(
@@ -421,7 +578,18 @@ impl Website {
                     FileName::Index,
                     Some(category.id_string.clone()),
                     move |ctx, document| {
-                        Website::render_page(ctx, document, category_nav, contentbox(content))
+                        let mut content: Vec<Box<dyn Html>> = vec![
+                            Box::new(html_heading(1, html_text(title))),
+                            Box::new(html_text(description)),
+                        ];
+                        if let Some(post_list) = &post_list {
+                            content.push(Box::new(postlist(
+                                post_list.iter()
+                                    .map(|(post_id, post_date, post_title, post_excerpt)| PostListEntry { post_id, post_date, post_title, post_excerpt })
+                                    .collect()
+                            )));
+                        }
+                        Website::render_page(ctx, document, category_nav, false, contentbox(content))
                     },
                 ).into()
             );
@@ -437,17 +605,39 @@ impl Website {
                     FileName::ID,
                     Some(post.metadata.category.clone()),
                     move |ctx, document| {
-                        Website::render_page(ctx, document, post_nav.clone(), contentbox(post.render_content(ctx)))
+                        Website::render_page(ctx, document, post_nav.clone(), post.metadata.published == Published::False, contentbox(post.render_content(ctx)))
                     },
                 ).into()
             );
         }
 
+        // Pages in unlisted categories, including subcategories of unlisted categories, and unlisted posts are left out
+        let unlisted_categories = self.categories.iter()
+            .flat_map(Category::iter_unlisted)
+            .map(|category| category.id_string.as_str())
+            .collect::<HashSet<_>>();
+        let sitemap_entries = documents.iter()
+            .filter_map(|document| if let Document::HTML(html) = document { Some(html) } else { None })
+            .filter(|html| html.category.as_deref().map_or(true, |category| !unlisted_categories.contains(category)))
+            .filter(|html| self.posts.get(&html.id).map_or(true, |post| post.metadata.published == Published::True))
+            .map(|html| SitemapEntry {
+                link: Link::ID(html.id.clone()),
+                last_modified: self.posts.get(&html.id).map(|post| post.metadata.date),
+            })
+            .collect::<Vec<_>>();
+        documents.push(
+            SitemapDocument::new(
+                "sitemap".to_string(),
+                FileName::Custom("sitemap"),
+                move |_, _| Box::new(SitemapRender(sitemap_entries)),
+            ).into()
+        );
+
         documents
     }
 
-    pub fn build(mut self, stylesheet: CSSBuilder) -> Result<WebsiteBuilder, String> {
-        self.validate();
+    pub fn build(mut self, stylesheet: CSSBuilder, diagnostics: &mut Diagnostics) -> Result<WebsiteBuilder, String> {
+        self.validate(diagnostics);
 
         self.posts.sort_by(|_, left, _, right| DateTime::cmp(&left.metadata.date, &right.metadata.date).reverse());
 
@@ -461,7 +651,8 @@ impl Website {
         let mut routes = HashMap::<String, Vec<String>>::new();
         let mut route_set = HashSet::<Vec<String>>::new();
 
-        for document in self.documents() {
+        let documents = self.documents();
+        for document in &documents {
             if !id_set.insert(document.id().to_string()) {
                 Err(format!("duplicate document ID: {}", document.id()))?;
             }
@@ -477,7 +668,10 @@ impl Website {
                 FileName::Resource => {
                     route.push("rsc".to_string());
                     let id = document.id();
-                    route.push(id.strip_prefix("resource:").unwrap_or(id).to_string() + document.extension());
+                    // Resources of a post are kept in a directory named after the post, `rsc/post/image.png`
+                    let path = id.strip_prefix("resource:").unwrap_or(id);
+                    route.extend(path.split('/').map(str::to_string));
+                    *route.last_mut().expect("resource route cannot be empty") += document.extension();
                 },
                 FileName::Custom(filename) => route.push(filename.to_string() + document.extension()),
             }
@@ -497,13 +691,15 @@ impl Website {
         }
 
         // Routes valid from here
-        let documents = self.documents();
+        self.validate_links(&routes, diagnostics);
 
-        let context = WebsiteRenderContext {
+        let context = WebsiteContext {
             title: self.title,
-            current_page: None,
+            base_url: self.base_url,
+            live_reload: self.live_reload,
+            image_hosts: self.image_hosts,
+            snippet_root: self.snippet_root,
             document_titles: HashMap::from_iter(documents.iter().filter_map(|document| document.title().map(|title| (document.id().to_string(), title.to_string())))),
-            stylesheet,
             global_scripts: documents.iter().filter_map(|document| {
                 if let Document::Resource(script) = document && script.resource.resource_type.is_global_script() {
                     Some(Link::ID(script.resource.id.clone()))
@@ -512,49 +708,91 @@ impl Website {
                 }
             }).collect(),
             stylesheet_link: Link::ID("stylesheet".to_string()),
+            feeds: documents.iter().filter_map(|document| {
+                if let Document::Feed(feed) = document {
+                    Some((feed.category.clone(), feed.format, Link::ID(feed.id.clone())))
+                } else {
+                    None
+                }
+            }).collect(),
             routes,
-            categories: self.categories
+            sources: self.posts.iter().map(|(id, post)| (id.clone(), post.source.clone())).collect(),
+            categories: self.categories,
         };
 
-        return Ok(WebsiteBuilder::new(context, documents));
+        return Ok(WebsiteBuilder::new(context, stylesheet, documents));
     }
 }
 
-pub struct WebsiteRenderContext {
+pub struct WebsiteContext {
     title: String,
-    current_page: Option<String>,
-    stylesheet: CSSBuilder,
+    base_url: String,
+    live_reload: bool,
+    image_hosts: Option<Vec<String>>,
+    snippet_root: PathBuf,
     stylesheet_link: Link,
     document_titles: HashMap<String, String>,
     global_scripts: Vec<Link>,
+    /// Feeds by the category they syndicate, `None` for the site-wide feed
+    feeds: Vec<(Option<String>, FeedFormat, Link)>,
     categories: Vec<Category>,
     routes: HashMap<String, Vec<String>>,
+    sources: HashMap<String, PathBuf>,
 }
 
-impl WebsiteRenderContext {
+impl WebsiteContext {
     pub fn route(&self, page_ref: PageRef) -> Option<&Vec<String>> {
         self.routes.get(page_ref.0)
     }
+}
+
+/// CSS rules and diagnostics are collected per document, so that documents can be rendered independently and the results merged in document order
+pub struct WebsiteRenderContext<'a> {
+    website: &'a WebsiteContext,
+    current_page: String,
+    stylesheet: CSSBuilder,
+    diagnostics: RefCell<Diagnostics>,
+}
+
+impl<'a> WebsiteRenderContext<'a> {
+    fn new(website: &'a WebsiteContext, page_id: &str) -> Self {
+        Self { website, current_page: page_id.to_string(), stylesheet: CSSBuilder::new(), diagnostics: RefCell::new(Diagnostics::new()) }
+    }
 
-    fn set_page(&mut self, page_id: &str) {
-        self.current_page = Some(page_id.to_string());
+    fn report_for_page(&self, page: PageRef, message: String) {
+        let mut diagnostic = Diagnostic::error(message);
+        if let Some(source) = self.website.sources.get(page.0) {
+            diagnostic = diagnostic.source(source);
+        }
+        self.report(diagnostic);
     }
 }
 
-impl RenderContext for WebsiteRenderContext {
+fn split_fragment(target: &str) -> (&str, Option<&str>) {
+    match target.split_once('#') {
+        Some((id, fragment)) => (id, Some(fragment)),
+        None => (target, None)
+    }
+}
+
+impl RenderContext for WebsiteRenderContext<'_> {
     fn title(&self) -> &str {
-        &self.title
+        &self.website.title
     }
 
     fn title_prefix(&self) -> Option<&str> {
-        Some(&self.title)
+        Some(&self.website.title)
     }
 
     fn resolve_href(&self, link: &Link, from_page: PageRef) -> HRef {
         match link {
             Link::ID(id) => {
-                let from = self.routes.get(from_page.0).expect(&*format!("invalid page reference: {}", from_page));
-                let to = self.routes.get(id).expect(&*format!("invalid page reference: {}", id));
+                let from = self.website.routes.get(from_page.0).expect(&*format!("invalid page reference: {}", from_page));
+                let (id, fragment) = split_fragment(id);
+                let Some(to) = self.website.routes.get(id) else {
+                    // Unknown targets are reported before rendering, see [Website::validate_links]
+                    return HRef("#".to_string());
+                };
 
                 let mut route = String::new();
                 let start_index = from.iter().zip(to).take_while(|(a, b)| a == b).count();
@@ -567,22 +805,46 @@ impl RenderContext for WebsiteRenderContext {
                     }
                     route += &*to[start_index..].join("/");
                 }
+                if let Some(fragment) = fragment {
+                    route = format!("{}#{}", route, fragment);
+                }
                 HRef(route)
             }
             Link::Custom { destination, .. } => destination.clone()
         }
     }
+
+    fn resolve_url(&self, link: &Link) -> HRef {
+        match link {
+            Link::ID(id) => {
+                let (id, fragment) = split_fragment(id);
+                let Some(route) = self.website.routes.get(id) else {
+                    return HRef("#".to_string());
+                };
+                let url = format!("{}/{}", self.website.base_url.trim_end_matches('/'), route.join("/"));
+                HRef(match fragment {
+                    Some(fragment) => format!("{}#{}", url, fragment),
+                    None => url
+                })
+            }
+            Link::Custom { destination, .. } => destination.clone()
+        }
+    }
+
     fn resolve_link_title(&self, link: &Link) -> String {
         match link {
-            Link::ID(id) => self.document_titles.get(id)
-                .expect(&*
-                    if self.routes.contains_key(id) {
-                        format!("Attempt to resolve link to document without title for {:?}", link)
-                    } else {
-                        format!("Attempt to resolve link to unknown ID for {:?}", link)
+            Link::ID(id) => {
+                let (id, _) = split_fragment(id);
+                if let Some(title) = self.website.document_titles.get(id) {
+                    title.clone()
+                } else {
+                    // Unknown IDs are reported before rendering
+                    if self.website.routes.contains_key(id) {
+                        self.report_for_page(self.current_page(), format!("link to document `{}` which has no title", id));
                     }
-                )
-                .clone(),
+                    id.to_string()
+                }
+            }
             Link::Custom { link_title: name, .. } => name.clone()
         }
     }
@@ -592,7 +854,7 @@ impl RenderContext for WebsiteRenderContext {
     }
 
     fn resolve_category(&self, category_id: &str) -> &Category {
-        self.categories.iter()
+        self.website.categories.iter()
             .flat_map(Category::iter_recurse)
             .find(|category| category.id_string == category_id)
             .expect(&*format!("attempt to resolve unknown category `{}`", category_id))
@@ -600,11 +862,11 @@ impl RenderContext for WebsiteRenderContext {
 
 
     fn current_page(&self) -> PageRef {
-        if let Some(page) = &self.current_page {
-            PageRef(&*page)
-        } else {
-            panic!("Rendering with unset current-page");
-        }
+        PageRef(&*self.current_page)
+    }
+
+    fn set_current_page(&mut self, page_id: &str) {
+        self.current_page = page_id.to_string();
     }
 
     fn stylesheet(&mut self) -> &mut CSSBuilder {
@@ -612,41 +874,129 @@ impl RenderContext for WebsiteRenderContext {
     }
 
     fn stylesheet_link(&self, for_page: PageRef) -> HRef {
-        self.resolve_href(&self.stylesheet_link, for_page)
+        self.resolve_href(&self.website.stylesheet_link, for_page)
     }
 
     fn global_scripts(&self, for_page: PageRef) -> Vec<HRef> {
-        self.global_scripts.iter().map(|link| self.resolve_href(&link, for_page)).collect()
+        self.website.global_scripts.iter().map(|link| self.resolve_href(&link, for_page)).collect()
+    }
+
+    fn feeds(&self, for_page: PageRef) -> Vec<(String, FeedFormat, HRef)> {
+        // Route of a page is its category path followed by the filename
+        let category_path = self.website.routes.get(for_page.0)
+            .map(|route| &route[..route.len() - 1])
+            .unwrap_or(&[]);
+
+        self.website.feeds.iter()
+            .filter(|(category, _, _)| category.as_ref().map_or(true, |category| category_path.contains(category)))
+            .map(|(_, format, link)| (self.resolve_link_title(link), *format, self.resolve_href(link, for_page)))
+            .collect()
+    }
+
+    fn live_reload(&self) -> Option<HRef> {
+        self.website.live_reload.then(|| HRef(crate::serve::LIVE_RELOAD_PATH.to_string()))
+    }
+
+    fn image_hosts(&self) -> Option<&[String]> {
+        self.website.image_hosts.as_deref()
+    }
+
+    fn snippet_root(&self) -> &Path {
+        &self.website.snippet_root
     }
+
+    fn report(&self, diagnostic: Diagnostic) {
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+}
+
+pub struct RenderedDocument {
+    pub route: Vec<String>,
+    pub content: Vec<u8>,
+}
+
+struct DocumentRender {
+    route: Vec<String>,
+    is_stylesheet: bool,
+    content: std::io::Result<Vec<u8>>,
+    stylesheet: CSSBuilder,
+    diagnostics: Diagnostics,
 }
 
 pub struct WebsiteBuilder {
-    context: WebsiteRenderContext,
-    documents: std::vec::IntoIter<Document>,
+    context: WebsiteContext,
+    stylesheet: CSSBuilder,
+    documents: Vec<Document>,
 }
 
 impl WebsiteBuilder {
-    pub fn new(context: WebsiteRenderContext, documents: Vec<Document>) -> WebsiteBuilder {
-        WebsiteBuilder {
-            context,
-            documents: documents.into_iter(),
-        }
+    pub fn new(context: WebsiteContext, stylesheet: CSSBuilder, documents: Vec<Document>) -> WebsiteBuilder {
+        WebsiteBuilder { context, stylesheet, documents }
     }
 
     pub fn stylesheet(&mut self) -> &mut CSSBuilder {
-        &mut self.context.stylesheet
+        &mut self.stylesheet
     }
 
-    pub fn into_stylesheet(self) -> CSSBuilder {
-        self.context.stylesheet
+    fn render_document(context: &WebsiteContext, document: Document) -> DocumentRender {
+        let mut render_context = WebsiteRenderContext::new(context, document.id());
+        let route = context.route(document.page_ref()).expect("all documents must have a route").clone();
+        let is_stylesheet = matches!(document, Document::Css(_));
+
+        util::reset_unique_ids();
+        let mut content = Vec::new();
+        let result = document.build(&render_context).render(&mut render_context, &mut content);
+
+        DocumentRender {
+            route,
+            is_stylesheet,
+            content: result.map(|_| content),
+            stylesheet: render_context.stylesheet,
+            diagnostics: render_context.diagnostics.into_inner(),
+        }
     }
 
-    pub fn next(&mut self) -> Option<(&mut WebsiteRenderContext, Document)> {
-        if let Some(document) = self.documents.next() {
-            self.context.set_page(document.id());
-            Some((&mut self.context, document))
-        } else {
-            None
+    /// Output is identical regardless of the number of threads, as CSS rules and diagnostics are merged in document order
+    pub fn render(self, threads: usize) -> Result<(Vec<RenderedDocument>, Diagnostics), String> {
+        let WebsiteBuilder { context, mut stylesheet, documents } = self;
+        let document_count = documents.len();
+        let queue = Mutex::new(documents.into_iter().enumerate());
+
+        let mut renders = std::thread::scope(|scope| {
+            let workers = (0..threads.max(1)).map(|_| scope.spawn(|| {
+                let mut renders = Vec::new();
+                loop {
+                    let Some((index, document)) = queue.lock().unwrap().next() else {
+                        break renders;
+                    };
+                    renders.push((index, WebsiteBuilder::render_document(&context, document)));
+                }
+            })).collect::<Vec<_>>();
+
+            workers.into_iter()
+                .flat_map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                .collect::<Vec<_>>()
+        });
+        renders.sort_by_key(|(index, _)| *index);
+        debug_assert_eq!(renders.len(), document_count);
+
+        let mut diagnostics = Diagnostics::new();
+        for (_, render) in &mut renders {
+            stylesheet.merge(std::mem::replace(&mut render.stylesheet, CSSBuilder::new()));
+            diagnostics.extend(std::mem::take(&mut render.diagnostics));
+        }
+
+        let mut stylesheet_content = Vec::new();
+        stylesheet.write(&mut stylesheet_content).map_err(|e| format!("error writing stylesheet: {}", e))?;
+
+        let mut rendered = Vec::with_capacity(renders.len());
+        for (_, render) in renders {
+            let mut content = render.content.map_err(|e| format!("error rendering {}: {}", render.route.join("/"), e))?;
+            if render.is_stylesheet {
+                content = stylesheet_content.clone();
+            }
+            rendered.push(RenderedDocument { route: render.route, content });
         }
+        Ok((rendered, diagnostics))
     }
 }